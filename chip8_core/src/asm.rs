@@ -0,0 +1,141 @@
+// Builders for individual opcodes, named after their mnemonics in Cowgod's
+// reference, for writing small programs in Rust rather than as raw hex:
+//
+//     let rom = asm::to_bytes(&[asm::ld_vx_byte(0, 5), asm::ld_f_vx(0), asm::drw(1, 2, 5)]);
+//
+// Register numbers and nibbles are masked to 4 bits and addresses to 12 bits,
+// so out-of-range arguments can't spill into the rest of the opcode
+
+fn op_x(base: u16, x: u8) -> u16 {
+    base | ((x as u16 & 0xF) << 8)
+}
+
+fn op_xy(base: u16, x: u8, y: u8) -> u16 {
+    op_x(base, x) | ((y as u16 & 0xF) << 4)
+}
+
+fn op_xnn(base: u16, x: u8, nn: u8) -> u16 {
+    op_x(base, x) | nn as u16
+}
+
+fn op_nnn(base: u16, addr: u16) -> u16 {
+    base | (addr & 0x0FFF)
+}
+
+// A program's opcodes as the big-endian bytes `Emu::load` expects
+pub fn to_bytes(program: &[u16]) -> Vec<u8> {
+    program.iter().flat_map(|op| op.to_be_bytes()).collect()
+}
+
+// 00CN
+pub fn scd(n: u8) -> u16 { 0x00C0 | (n as u16 & 0xF) }
+// 00DN
+pub fn scu(n: u8) -> u16 { 0x00D0 | (n as u16 & 0xF) }
+// 00E0
+pub fn cls() -> u16 { 0x00E0 }
+// 00EE
+pub fn ret() -> u16 { 0x00EE }
+// 00FB
+pub fn scr() -> u16 { 0x00FB }
+// 00FC
+pub fn scl() -> u16 { 0x00FC }
+// 00FE
+pub fn low() -> u16 { 0x00FE }
+// 00FF
+pub fn high() -> u16 { 0x00FF }
+// 1NNN
+pub fn jp(addr: u16) -> u16 { op_nnn(0x1000, addr) }
+// 2NNN
+pub fn call(addr: u16) -> u16 { op_nnn(0x2000, addr) }
+// 3XNN
+pub fn se_vx_byte(x: u8, nn: u8) -> u16 { op_xnn(0x3000, x, nn) }
+// 4XNN
+pub fn sne_vx_byte(x: u8, nn: u8) -> u16 { op_xnn(0x4000, x, nn) }
+// 5XY0
+pub fn se_vx_vy(x: u8, y: u8) -> u16 { op_xy(0x5000, x, y) }
+// 6XNN
+pub fn ld_vx_byte(x: u8, nn: u8) -> u16 { op_xnn(0x6000, x, nn) }
+// 7XNN
+pub fn add_vx_byte(x: u8, nn: u8) -> u16 { op_xnn(0x7000, x, nn) }
+// 8XY0
+pub fn ld_vx_vy(x: u8, y: u8) -> u16 { op_xy(0x8000, x, y) }
+// 8XY1
+pub fn or(x: u8, y: u8) -> u16 { op_xy(0x8001, x, y) }
+// 8XY2
+pub fn and(x: u8, y: u8) -> u16 { op_xy(0x8002, x, y) }
+// 8XY3
+pub fn xor(x: u8, y: u8) -> u16 { op_xy(0x8003, x, y) }
+// 8XY4
+pub fn add_vx_vy(x: u8, y: u8) -> u16 { op_xy(0x8004, x, y) }
+// 8XY5
+pub fn sub(x: u8, y: u8) -> u16 { op_xy(0x8005, x, y) }
+// 8XY6
+pub fn shr(x: u8, y: u8) -> u16 { op_xy(0x8006, x, y) }
+// 8XY7
+pub fn subn(x: u8, y: u8) -> u16 { op_xy(0x8007, x, y) }
+// 8XYE
+pub fn shl(x: u8, y: u8) -> u16 { op_xy(0x800E, x, y) }
+// 9XY0
+pub fn sne_vx_vy(x: u8, y: u8) -> u16 { op_xy(0x9000, x, y) }
+// ANNN
+pub fn ld_i(addr: u16) -> u16 { op_nnn(0xA000, addr) }
+// BNNN
+pub fn jp_v0(addr: u16) -> u16 { op_nnn(0xB000, addr) }
+// CXNN
+pub fn rnd(x: u8, nn: u8) -> u16 { op_xnn(0xC000, x, nn) }
+// DXYN
+pub fn drw(x: u8, y: u8, n: u8) -> u16 { op_xy(0xD000, x, y) | (n as u16 & 0xF) }
+// EX9E
+pub fn skp(x: u8) -> u16 { op_x(0xE09E, x) }
+// EXA1
+pub fn sknp(x: u8) -> u16 { op_x(0xE0A1, x) }
+// F000 NNNN: the address goes in the following word, e.g.
+// to_bytes(&[ld_i_long(), 0x1234])
+pub fn ld_i_long() -> u16 { 0xF000 }
+// FN01
+pub fn plane(n: u8) -> u16 { op_x(0xF001, n) }
+// F002
+pub fn audio() -> u16 { 0xF002 }
+// FX07
+pub fn ld_vx_dt(x: u8) -> u16 { op_x(0xF007, x) }
+// FX0A
+pub fn ld_vx_k(x: u8) -> u16 { op_x(0xF00A, x) }
+// FX15
+pub fn ld_dt_vx(x: u8) -> u16 { op_x(0xF015, x) }
+// FX18
+pub fn ld_st_vx(x: u8) -> u16 { op_x(0xF018, x) }
+// FX1E
+pub fn add_i_vx(x: u8) -> u16 { op_x(0xF01E, x) }
+// FX29
+pub fn ld_f_vx(x: u8) -> u16 { op_x(0xF029, x) }
+// FX30
+pub fn ld_hf_vx(x: u8) -> u16 { op_x(0xF030, x) }
+// FX33
+pub fn ld_b_vx(x: u8) -> u16 { op_x(0xF033, x) }
+// FX3A
+pub fn pitch(x: u8) -> u16 { op_x(0xF03A, x) }
+// FX55: store V0 through Vx at I
+pub fn ld_i_vx(x: u8) -> u16 { op_x(0xF055, x) }
+// FX65: load V0 through Vx from I
+pub fn ld_vx_i(x: u8) -> u16 { op_x(0xF065, x) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assembles_a_program_to_big_endian_bytes() {
+        let program = [ld_vx_byte(0, 0x2A), ld_i(0x22A), drw(1, 2, 5)];
+
+        assert_eq!(program, [0x602A, 0xA22A, 0xD125]);
+        assert_eq!(to_bytes(&program), [0x60, 0x2A, 0xA2, 0x2A, 0xD1, 0x25]);
+    }
+
+    #[test]
+    fn out_of_range_arguments_are_masked() {
+        assert_eq!(jp(0x1234), 0x1234);
+        assert_eq!(jp(0xF234), 0x1234);
+        assert_eq!(ld_vx_byte(0x1F, 5), 0x6F05);
+        assert_eq!(drw(1, 2, 0x15), 0xD125);
+    }
+}
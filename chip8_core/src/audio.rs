@@ -0,0 +1,102 @@
+use std::f32::consts::PI;
+use std::str::FromStr;
+
+// Shape of the tone played while the sound timer runs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Waveform {
+    // The harsh buzz of the original hardware
+    #[default]
+    Square,
+    Sine,
+    Triangle,
+    Sawtooth
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "square" => Ok(Waveform::Square),
+            "sine" => Ok(Waveform::Sine),
+            "triangle" => Ok(Waveform::Triangle),
+            "sawtooth" => Ok(Waveform::Sawtooth),
+            _ => Err(format!("Unknown waveform '{}', expected square, sine, triangle, or sawtooth", s))
+        }
+    }
+}
+
+// The waveform's value at a point in its cycle, where phase runs from 0.0 to
+// 1.0 over one period. Always lies between -1.0 and 1.0
+pub fn audio_sample(waveform: Waveform, phase: f32) -> f32 {
+    let phase = phase.rem_euclid(1.0);
+
+    match waveform {
+        Waveform::Square => if phase <= 0.5 { 1.0 } else { -1.0 },
+        Waveform::Sine => (phase * 2.0 * PI).sin(),
+        Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+        Waveform::Sawtooth => 2.0 * phase - 1.0
+    }
+}
+
+// Fill a buffer with samples of the waveform at the given volume, advancing
+// phase by phase_inc (frequency / sample rate) for each sample. phase is
+// carried between calls so consecutive buffers join up without clicks
+pub fn generate_audio(waveform: Waveform, out: &mut [f32], phase: &mut f32, phase_inc: f32, volume: f32) {
+    for x in out.iter_mut() {
+        *x = audio_sample(waveform, *phase) * volume;
+        *phase = (*phase + phase_inc) % 1.0;
+    }
+}
+
+// Samples per second at which XO-CHIP plays its audio pattern for an FX3A
+// pitch: 4000 at the default of 64, going up an octave every 48 steps
+pub fn pattern_playback_rate(pitch: u8) -> f32 {
+    4000.0 * 2f32.powf((pitch as f32 - 64.0) / 48.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WAVEFORMS: [Waveform; 4] = [Waveform::Square, Waveform::Sine, Waveform::Triangle, Waveform::Sawtooth];
+
+    #[test]
+    fn samples_stay_within_range() {
+        for waveform in WAVEFORMS {
+            for i in 0..=1000 {
+                let sample = audio_sample(waveform, i as f32 / 1000.0);
+                assert!((-1.0..=1.0).contains(&sample), "{:?} gave {} at step {}", waveform, sample, i);
+            }
+        }
+    }
+
+    #[test]
+    fn square_wave_only_takes_two_values() {
+        for i in 0..100 {
+            let sample = audio_sample(Waveform::Square, i as f32 / 100.0);
+            assert!(sample == 1.0 || sample == -1.0);
+        }
+    }
+
+    #[test]
+    fn generated_audio_is_scaled_by_volume() {
+        for waveform in WAVEFORMS {
+            let mut out = [0.0; 256];
+            let mut phase = 0.0;
+            generate_audio(waveform, &mut out, &mut phase, 440.0 / 44100.0, 0.25);
+
+            assert!(out.iter().all(|x| (-0.25..=0.25).contains(x)), "{:?} went past the volume", waveform);
+            assert!(out.iter().any(|x| x.abs() > 0.2), "{:?} never got near the volume", waveform);
+        }
+    }
+
+    #[test]
+    fn parses_waveform_names() {
+        assert_eq!("square".parse(), Ok(Waveform::Square));
+        assert_eq!("Sine".parse(), Ok(Waveform::Sine));
+        assert_eq!("TRIANGLE".parse(), Ok(Waveform::Triangle));
+        assert_eq!("sawtooth".parse(), Ok(Waveform::Sawtooth));
+        assert!("noise".parse::<Waveform>().is_err());
+    }
+}
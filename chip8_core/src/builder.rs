@@ -0,0 +1,163 @@
+use crate::{Emu, Quirks, BIG_FONTSET_SIZE, FONTSET_ADDR, FONTSET_SIZE, RAM_SIZE, START_ADDR};
+
+// Sets up an emulator in one go rather than through a string of setters
+// after Emu::new. Anything left unset keeps Emu::new's default:
+//
+//     let emu = EmuBuilder::new().quirks(schip_quirks).seed(42).memory_size(0x10000).build();
+#[derive(Clone, Debug)]
+pub struct EmuBuilder {
+    quirks: Quirks,
+    seed: Option<u64>,                           // None seeds from the OS
+    memory_size: usize,
+    start_addr: u16,
+    font_addr: u16,
+    small_font: Option<[u8; FONTSET_SIZE]>,      // None keeps the built-in font
+    big_font: Option<[u8; BIG_FONTSET_SIZE]>     // None keeps the built-in font
+}
+
+impl Default for EmuBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EmuBuilder {
+    pub fn new() -> Self {
+        Self {
+            quirks: Quirks::default(),
+            seed: None,
+            memory_size: RAM_SIZE,
+            start_addr: START_ADDR,
+            font_addr: FONTSET_ADDR,
+            small_font: None,
+            big_font: None
+        }
+    }
+
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    // Seed CXNN's random numbers, as with Emu::with_seed
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    // Bytes of RAM, as with Emu::with_memory_size. build panics unless it's
+    // between RESERVED_SIZE and MAX_RAM_SIZE
+    pub fn memory_size(mut self, bytes: usize) -> Self {
+        self.memory_size = bytes;
+        self
+    }
+
+    // Where load puts ROMs and where PC starts and returns to on reset, such
+    // as 0x600 for the ETI-660. build panics unless it's in RAM and above the
+    // fonts
+    pub fn start_address(mut self, addr: u16) -> Self {
+        self.start_addr = addr;
+        self
+    }
+
+    // Where the fonts go, as with Emu::set_font_address. build panics if they
+    // don't fit below START_ADDR
+    pub fn font_address(mut self, addr: u16) -> Self {
+        self.font_addr = addr;
+        self
+    }
+
+    // Digit sprites for FX29, as with Emu::set_small_font
+    pub fn small_font(mut self, font: [u8; FONTSET_SIZE]) -> Self {
+        self.small_font = Some(font);
+        self
+    }
+
+    // Big digit sprites for FX30, as with Emu::set_big_font
+    pub fn big_font(mut self, font: [u8; BIG_FONTSET_SIZE]) -> Self {
+        self.big_font = Some(font);
+        self
+    }
+
+    pub fn build(self) -> Emu {
+        let mut emu = Emu::with_memory_size(self.memory_size);
+        emu.set_quirks(self.quirks);
+        if let Some(seed) = self.seed {
+            emu.reseed(seed);
+        }
+        if let Err(e) = emu.set_font_address(self.font_addr) {
+            panic!("can't put the fonts at {:#05x}: {}", self.font_addr, e);
+        }
+        let fonts_end = self.font_addr as usize + FONTSET_SIZE + BIG_FONTSET_SIZE;
+        if self.start_addr as usize >= self.memory_size || (self.start_addr as usize) < fonts_end {
+            panic!("can't start programs at {:#05x}: it has to be between the fonts' end at {:#05x} and the end of memory",
+                self.start_addr, fonts_end);
+        }
+        emu.start_addr = self.start_addr;
+        emu.pc = self.start_addr;
+        if let Some(font) = self.small_font {
+            emu.set_small_font(font);
+        }
+        if let Some(font) = self.big_font {
+            emu.set_big_font(font);
+        }
+
+        emu
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+
+    #[test]
+    fn start_address_moves_pc_and_reset() {
+        let mut emu = EmuBuilder::new().seed(0).start_address(0x600).build();
+        assert_eq!(emu.program_counter(), 0x600);
+
+        emu.reset();
+        assert_eq!(emu.program_counter(), 0x600);
+        assert_eq!(emu.start_address(), 0x600);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't start programs at 0x1000")]
+    fn start_address_past_the_end_of_ram_panics() {
+        EmuBuilder::new().seed(0).start_address(0x1000).build();
+    }
+
+    #[test]
+    #[should_panic(expected = "can't start programs at 0x060")]
+    fn start_address_over_the_fonts_panics() {
+        EmuBuilder::new().seed(0).font_address(0x050).start_address(0x060).build();
+    }
+
+    #[test]
+    fn build_applies_the_seed_memory_size_and_quirks() {
+        let quirks = Quirks { jump_uses_vx: true, ..Quirks::default() };
+        let rom = asm::to_bytes(&[asm::rnd(0, 0xFF), asm::rnd(1, 0xFF)]);
+
+        let mut emu = EmuBuilder::new().quirks(quirks).seed(42).memory_size(0x10000).build();
+        let mut seeded = Emu::with_seed(42);
+        emu.load(&rom).unwrap();
+        seeded.load(&rom).unwrap();
+        emu.run(2).unwrap();
+        seeded.run(2).unwrap();
+
+        assert_eq!(emu.registers()[..2], seeded.registers()[..2]);
+        assert_eq!(emu.read_ram(0xFFFF), Some(0));
+        assert_eq!(emu.quirks(), quirks);
+    }
+
+    #[test]
+    fn unset_options_keep_the_defaults() {
+        let emu = EmuBuilder::new().build();
+
+        assert_eq!(emu.read_ram(0xFFF), Some(0));
+        assert_eq!(emu.read_ram(0x1000), None);
+        assert_eq!(emu.quirks(), Quirks::default());
+        assert_eq!(emu.program_counter(), START_ADDR);
+        assert_eq!(emu.font_address(), FONTSET_ADDR);
+    }
+}
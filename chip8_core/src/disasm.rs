@@ -0,0 +1,230 @@
+use std::collections::BTreeSet;
+
+use crate::START_ADDR;
+
+// Render a single opcode as a mnemonic, e.g. "LD V3, 0x2A" or "DRW V1, V2, 5".
+// Opcodes the emulator doesn't implement come out as "UNKNOWN 0xXXXX"
+pub fn disassemble(op: u16) -> String {
+    render(op, &BTreeSet::new())
+}
+
+// Disassemble a whole ROM, as loaded at START_ADDR, into a listing with one
+// instruction per line, e.g. "0x0200: A22A  LD I, 0x22A". An odd-length ROM
+// ends with a line for its trailing byte
+pub fn disassemble_listing(rom: &[u8]) -> String {
+    listing(rom, &BTreeSet::new())
+}
+
+// Like disassemble_listing, but addresses that are jumped to, called, or
+// pointed at by LD I get a label such as "L_0x2A4:", and instructions refer
+// to them by it
+pub fn disassemble_with_labels(rom: &[u8]) -> String {
+    let start = START_ADDR as usize;
+
+    // First pass: find every address the program refers to
+    let mut labels = BTreeSet::new();
+    for (_, op) in opcodes(rom) {
+        if let Some(target) = referenced_addr(op) {
+            let target_offset = (target as usize).wrapping_sub(start);
+
+            // Only label addresses that land on a whole instruction in the
+            // listing, not on the trailing byte of an odd-length ROM
+            if target_offset.is_multiple_of(2) && target_offset + 1 < rom.len() {
+                labels.insert(target);
+            }
+        }
+    }
+
+    listing(rom, &labels)
+}
+
+// The listing of a ROM, with the given addresses labelled
+fn listing(rom: &[u8], labels: &BTreeSet<u16>) -> String {
+    let start = START_ADDR as usize;
+
+    let mut out = String::new();
+    for (offset, op) in opcodes(rom) {
+        let addr = (start + offset) as u16;
+
+        if labels.contains(&addr) {
+            out.push_str(&format!("{}:\n", label(addr)));
+        }
+
+        out.push_str(&format!("{:#06X}: {:04X}  {}\n", addr, op, render(op, labels)));
+    }
+
+    if rom.len() % 2 == 1 {
+        let offset = rom.len() - 1;
+        out.push_str(&format!("{:#06X}: {:02X}    (trailing byte)\n", start + offset, rom[offset]));
+    }
+
+    out
+}
+
+// Each complete two-byte opcode in the ROM along with its offset
+fn opcodes(rom: &[u8]) -> impl Iterator<Item = (usize, u16)> + '_ {
+    rom.chunks_exact(2)
+        .enumerate()
+        .map(|(i, pair)| (i * 2, ((pair[0] as u16) << 8) | pair[1] as u16))
+}
+
+// The address an instruction refers to directly, if any
+fn referenced_addr(op: u16) -> Option<u16> {
+    match op & 0xF000 {
+        0x1000 | 0x2000 | 0xA000 => Some(op & 0x0FFF),
+        _ => None
+    }
+}
+
+fn label(addr: u16) -> String {
+    format!("L_{:#05X}", addr)
+}
+
+fn render(op: u16, labels: &BTreeSet<u16>) -> String {
+    let digit1 = (op & 0xF000) >> 12;
+    let digit2 = (op & 0x0F00) >> 8;
+    let digit3 = (op & 0x00F0) >> 4;
+    let digit4 = op & 0x000F;
+
+    let x = digit2;
+    let y = digit3;
+    let nn = op & 0x00FF;
+    let nnn = op & 0x0FFF;
+
+    // Show an address by its label if it has one
+    let addr = |a: u16| {
+        if labels.contains(&a) {
+            label(a)
+        } else {
+            format!("{:#05X}", a)
+        }
+    };
+
+    match (digit1, digit2, digit3, digit4) {
+        (0,0,0,0) => "NOP".to_string(),
+        (0,0,0xC,_) => format!("SCD {}", digit4),
+        (0,0,0xD,_) => format!("SCU {}", digit4),
+        (0,0,0xE,0) => "CLS".to_string(),
+        (0,0,0xE,0xE) => "RET".to_string(),
+        (0,0,0xF,0xB) => "SCR".to_string(),
+        (0,0,0xF,0xC) => "SCL".to_string(),
+        (0,0,0xF,0xE) => "LOW".to_string(),
+        (0,0,0xF,0xF) => "HIGH".to_string(),
+        (0,0,_,_) => format!("UNKNOWN {:#06X}", op),
+        (0,_,_,_) => format!("SYS {}", addr(nnn)),
+        (1,_,_,_) => format!("JP {}", addr(nnn)),
+        (2,_,_,_) => format!("CALL {}", addr(nnn)),
+        (3,_,_,_) => format!("SE V{:X}, {:#04X}", x, nn),
+        (4,_,_,_) => format!("SNE V{:X}, {:#04X}", x, nn),
+        (5,_,_,0) => format!("SE V{:X}, V{:X}", x, y),
+        (6,_,_,_) => format!("LD V{:X}, {:#04X}", x, nn),
+        (7,_,_,_) => format!("ADD V{:X}, {:#04X}", x, nn),
+        (8,_,_,0) => format!("LD V{:X}, V{:X}", x, y),
+        (8,_,_,1) => format!("OR V{:X}, V{:X}", x, y),
+        (8,_,_,2) => format!("AND V{:X}, V{:X}", x, y),
+        (8,_,_,3) => format!("XOR V{:X}, V{:X}", x, y),
+        (8,_,_,4) => format!("ADD V{:X}, V{:X}", x, y),
+        (8,_,_,5) => format!("SUB V{:X}, V{:X}", x, y),
+        (8,_,_,6) => format!("SHR V{:X}, V{:X}", x, y),
+        (8,_,_,7) => format!("SUBN V{:X}, V{:X}", x, y),
+        (8,_,_,0xE) => format!("SHL V{:X}, V{:X}", x, y),
+        (9,_,_,0) => format!("SNE V{:X}, V{:X}", x, y),
+        (0xA,_,_,_) => format!("LD I, {}", addr(nnn)),
+        (0xB,_,_,_) => format!("JP V0, {:#05X}", nnn),
+        (0xC,_,_,_) => format!("RND V{:X}, {:#04X}", x, nn),
+        (0xD,_,_,_) => format!("DRW V{:X}, V{:X}, {}", x, y, digit4),
+        (0xE,_,0x9,0xE) => format!("SKP V{:X}", x),
+        (0xE,_,0xA,0x1) => format!("SKNP V{:X}", x),
+        (0xF,0x0,0x0,0x0) => "LD I, LONG".to_string(),
+        (0xF,_,0x0,0x1) => format!("PLANE {}", x),
+        (0xF,0x0,0x0,0x2) => "AUDIO".to_string(),
+        (0xF,_,0x0,0x7) => format!("LD V{:X}, DT", x),
+        (0xF,_,0x0,0xA) => format!("LD V{:X}, K", x),
+        (0xF,_,0x1,0x5) => format!("LD DT, V{:X}", x),
+        (0xF,_,0x1,0x8) => format!("LD ST, V{:X}", x),
+        (0xF,_,0x1,0xE) => format!("ADD I, V{:X}", x),
+        (0xF,_,0x2,0x9) => format!("LD F, V{:X}", x),
+        (0xF,_,0x3,0x0) => format!("LD HF, V{:X}", x),
+        (0xF,_,0x3,0x3) => format!("LD B, V{:X}", x),
+        (0xF,_,0x3,0xA) => format!("PITCH V{:X}", x),
+        (0xF,_,0x5,0x5) => format!("LD [I], V{:X}", x),
+        (0xF,_,0x6,0x5) => format!("LD V{:X}, [I]", x),
+        (_,_,_,_) => format!("UNKNOWN {:#06X}", op)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+
+    #[test]
+    fn renders_representative_opcodes() {
+        assert_eq!(disassemble(0x00E0), "CLS");
+        assert_eq!(disassemble(0x00EE), "RET");
+        assert_eq!(disassemble(0x1234), "JP 0x234");
+        assert_eq!(disassemble(0x632A), "LD V3, 0x2A");
+        assert_eq!(disassemble(0x8124), "ADD V1, V2");
+        assert_eq!(disassemble(0xA22A), "LD I, 0x22A");
+        assert_eq!(disassemble(0xD125), "DRW V1, V2, 5");
+        assert_eq!(disassemble(0xE19E), "SKP V1");
+        assert_eq!(disassemble(0xF265), "LD V2, [I]");
+    }
+
+    #[test]
+    fn renders_unimplemented_opcodes_as_unknown() {
+        assert_eq!(disassemble(0x8128), "UNKNOWN 0x8128");
+        assert_eq!(disassemble(0x9121), "UNKNOWN 0x9121");
+        assert_eq!(disassemble(0xE0FF), "UNKNOWN 0xE0FF");
+        assert_eq!(disassemble(0xFFFF), "UNKNOWN 0xFFFF");
+    }
+
+    #[test]
+    fn labels_loop_targets() {
+        let rom = asm::to_bytes(&[
+            asm::ld_vx_byte(0, 0),
+            asm::add_vx_byte(0, 1),
+            asm::se_vx_byte(0, 10),
+            asm::jp(0x202),
+            asm::jp(0x208)
+        ]);
+
+        let listing = disassemble_with_labels(&rom);
+
+        assert_eq!(listing, "\
+0x0200: 6000  LD V0, 0x00
+L_0x202:
+0x0202: 7001  ADD V0, 0x01
+0x0204: 300A  SE V0, 0x0A
+0x0206: 1202  JP L_0x202
+L_0x208:
+0x0208: 1208  JP L_0x208
+");
+    }
+    #[test]
+    fn only_labels_addresses_inside_the_listing() {
+        // LD I points past the ROM, and the CALL at its trailing byte
+        let mut rom = asm::to_bytes(&[asm::ld_i(0x300), asm::call(0x204)]);
+        rom.push(0xAB);
+
+        let listing = disassemble_with_labels(&rom);
+
+        assert_eq!(listing, "\
+0x0200: A300  LD I, 0x300
+0x0202: 2204  CALL 0x204
+0x0204: AB    (trailing byte)
+");
+    }
+
+    #[test]
+    fn lists_each_instruction_and_a_trailing_byte() {
+        let mut rom = asm::to_bytes(&[asm::ld_i(0x22A), asm::jp(0x200)]);
+        rom.push(0xAB);
+
+        assert_eq!(disassemble_listing(&rom), "\
+0x0200: A22A  LD I, 0x22A
+0x0202: 1200  JP 0x200
+0x0204: AB    (trailing byte)
+");
+    }
+}
@@ -1,16 +1,52 @@
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use std::fmt;
+
+pub mod asm;
+pub mod audio;
+pub mod builder;
+pub mod disasm;
+pub mod lockstep;
+pub mod renderer;
+pub mod stats;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub use audio::{audio_sample, generate_audio, pattern_playback_rate, Waveform};
+pub use builder::EmuBuilder;
+pub use disasm::{disassemble, disassemble_listing, disassemble_with_labels};
+pub use lockstep::{find_divergence, Divergence};
+pub use renderer::Renderer;
+pub use stats::InstructionStats;
 
 pub const SCREEN_WIDTH: usize = 64;
 pub const SCREEN_HEIGHT: usize = 32;
 
+// SUPER-CHIP high resolution mode, switched on with 00FF
+pub const HIRES_SCREEN_WIDTH: usize = 128;
+pub const HIRES_SCREEN_HEIGHT: usize = 64;
+
+// The screen buffer is big enough for either mode. In low resolution only
+// the first SCREEN_WIDTH * SCREEN_HEIGHT pixels are used
+const SCREEN_BUF_SIZE: usize = HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT;
+
+// XO-CHIP draws on two bitplanes, giving four colors. Programs that never
+// select a plane with FN01 only ever touch the first
+pub const NUM_PLANES: usize = 2;
+
+// RAM is 4KB unless Emu::with_memory_size says otherwise. XO-CHIP programs
+// can use up to 64KB, as much as a 16-bit address reaches
 const RAM_SIZE: usize = 4096;
+pub const MAX_RAM_SIZE: usize = 0x10000;
 const NUM_REGS: usize = 16;
 const STACK_SIZE: usize = 16;
-const NUM_KEYS: usize = 16;
+pub const NUM_KEYS: usize = 16;
+
+// The fonts go at the bottom of memory unless moved with set_font_address,
+// small font first and then the big one
+const FONTSET_ADDR: u16 = 0x0;
 
-const FONTSET_SIZE: usize = 80;
+pub const FONTSET_SIZE: usize = 80;
 const FONT_SIZE: usize = 5;
-const FONTSET_ADDR: usize = 0x0;
 const FONTSET: [u8; FONTSET_SIZE] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
@@ -30,507 +66,3958 @@ const FONTSET: [u8; FONTSET_SIZE] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80 // F
 ];
 
+// SUPER-CHIP's 8x10 digits, which FX30 points at
+pub const BIG_FONTSET_SIZE: usize = 160;
+const BIG_FONT_SIZE: usize = 10;
+const BIG_FONTSET: [u8; BIG_FONTSET_SIZE] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0  // F
+];
+
+// XO-CHIP's sound is a loop of 128 one-bit samples, set with F002
+pub const AUDIO_PATTERN_SIZE: usize = 16;
+
+// FX3A pitch that plays the pattern at 4000 samples a second
+pub const DEFAULT_PITCH: u8 = 64;
+
+// Most pixels Emu::collision_pixels keeps between tick_timers calls, so a
+// headless run that never ends a frame doesn't grow the list without bound.
+// It's enough for every pixel of the hires display
+pub const MAX_COLLISION_PIXELS: usize = HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT;
+
+// One of the sixteen keys on the hex keypad
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Key {
+    Num0 = 0x0, Num1 = 0x1, Num2 = 0x2, Num3 = 0x3,
+    Num4 = 0x4, Num5 = 0x5, Num6 = 0x6, Num7 = 0x7,
+    Num8 = 0x8, Num9 = 0x9, A = 0xA, B = 0xB,
+    C = 0xC, D = 0xD, E = 0xE, F = 0xF
+}
+
+impl TryFrom<u8> for Key {
+    // The value that isn't a key
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        const KEYS: [Key; NUM_KEYS] = [
+            Key::Num0, Key::Num1, Key::Num2, Key::Num3,
+            Key::Num4, Key::Num5, Key::Num6, Key::Num7,
+            Key::Num8, Key::Num9, Key::A, Key::B,
+            Key::C, Key::D, Key::E, Key::F
+        ];
+
+        KEYS.get(value as usize).copied().ok_or(value)
+    }
+}
+
+// What a CALL does with every stack slot already in use
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StackOverflowPolicy {
+    // Stop with Chip8Error::StackOverflow
+    #[default]
+    Error,
+    // Drop the return address, so the call goes ahead but can't be returned from
+    Saturate,
+    // Start again from the bottom of the stack, overwriting the oldest entry
+    Wrap
+}
+
+// Behaviors that differ between CHIP-8 interpreters. Everything defaults
+// to what this emulator did before it could be configured, so existing ROMs
+// keep working, except that FX55/FX65 move I past the registers as the
+// original COSMAC VIP interpreter does. ROMs written for a particular
+// interpreter can switch to its behavior
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    pub add_imm_sets_vf: bool,          // 7XNN sets VF on overflow, like some buggy interpreters
+    pub load_store_increments_i: bool,  // FX55/FX65 leave I pointing past the last register (VIP), or unchanged (SCHIP)
+    pub shift_uses_vy: bool,            // 8XY6/8XYE shift Vy into Vx (VIP), or shift Vx in place (SCHIP)
+    pub jump_uses_vx: bool,             // BNNN jumps to NNN + V0 (VIP), or BXNN to XNN + Vx (SCHIP)
+    pub strict_alignment: bool,         // Fetching from an odd address is an error rather than allowed
+    pub clip_sprites: bool,             // DXYN cuts sprites off at the screen edges (VIP), or wraps them around
+    pub display_wait: bool,             // DXYN waits for the next frame before the program carries on (VIP)
+    pub add_i_overflow_sets_vf: bool,   // FX1E sets VF when I goes past 0xFFF, like the Amiga interpreter
+    pub stack_overflow: StackOverflowPolicy,    // What CALL does once the stack is full
+    pub wait_for_key_release: bool      // FX0A finishes once keys are let go (VIP), or as soon as one is down
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            add_imm_sets_vf: false,
+            load_store_increments_i: true,
+            shift_uses_vy: false,
+            jump_uses_vx: false,
+            strict_alignment: false,
+            clip_sprites: false,
+            display_wait: false,
+            add_i_overflow_sets_vf: false,
+            stack_overflow: StackOverflowPolicy::Error,
+            wait_for_key_release: false
+        }
+    }
+}
+
+// 64-bit FNV-1a. Used instead of std's hashers because its output is
+// fixed, so hashes can be compared across runs, machines, and Rust versions
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+// Errors the emulator can hit while running a program
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Chip8Error {
+    NopLimitExceeded(u16),          // Only found NOPs; holds the PC we stopped at
+    StackOverflow,                  // CALL with all stack slots in use
+    StackUnderflow,                 // RET with nothing on the stack
+    InvalidState,                   // Save state doesn't match this machine's layout
+    UnknownOpcode(u16),             // Holds the opcode we couldn't decode
+    PcOutOfBounds(u16),             // Tried to fetch an instruction from past the end of RAM
+    InvalidStack,                   // Imported stack or stack pointer doesn't fit the stack
+    MisalignedPc(u16),              // Tried to fetch from an odd address with strict_alignment on
+    VfRead(u16),                    // Holds the opcode that read VF as an operand, with set_trap_vf_reads on
+    AddressOutOfBounds(u16),        // write_ram was given an address past the end of RAM
+    IncompatibleStateVersion { found: u8, expected: u8 },  // Save state was written by a different state format
+    MemoryOutOfBounds { pc: u16, addr: usize },             // The instruction at pc reached for a byte past the end of RAM
+    MachineCodeCall(u16),           // 0NNN asked to run machine code at NNN, which can't be emulated
+    FontAddressOutOfRange(u16),     // Fonts placed at this address wouldn't end by START_ADDR
+    MissingOperand(u16)             // execute_opcode was given this opcode, whose operand is the word after it
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::NopLimitExceeded(pc) => {
+                write!(f, "No instruction found after skipping NOPs (stopped at {:#06x})", pc)
+            },
+            Chip8Error::StackOverflow => {
+                write!(f, "Stack overflow: more than {} nested subroutine calls", STACK_SIZE)
+            },
+            Chip8Error::StackUnderflow => {
+                write!(f, "Stack underflow: returned from a subroutine with an empty stack")
+            },
+            Chip8Error::InvalidState => {
+                write!(f, "Save state is corrupt or from an incompatible emulator")
+            },
+            Chip8Error::UnknownOpcode(op) => {
+                write!(f, "Unknown opcode {:#06x}", op)
+            },
+            Chip8Error::PcOutOfBounds(pc) => {
+                write!(f, "Program counter {:#06x} is outside of RAM", pc)
+            },
+            Chip8Error::InvalidStack => {
+                write!(f, "Stack must have at most {} entries and a stack pointer within it", STACK_SIZE)
+            },
+            Chip8Error::MisalignedPc(pc) => {
+                write!(f, "Program counter {:#06x} is not on an instruction boundary", pc)
+            },
+            Chip8Error::VfRead(op) => {
+                write!(f, "Opcode {:#06x} reads VF as an operand, but VF holds a flag", op)
+            },
+            Chip8Error::AddressOutOfBounds(addr) => {
+                write!(f, "Address {:#06x} is outside of RAM", addr)
+            },
+            Chip8Error::MachineCodeCall(addr) => {
+                write!(f, "Machine code routines aren't supported (0NNN called {:#05x})", addr)
+            },
+            Chip8Error::MemoryOutOfBounds { pc, addr } => {
+                write!(f, "Instruction at {:#06x} accessed address {:#06x}, which is outside of RAM", pc, addr)
+            },
+            Chip8Error::IncompatibleStateVersion { found, expected } => {
+                write!(f, "Save state uses format version {}, but this emulator reads version {}", found, expected)
+            },
+            Chip8Error::FontAddressOutOfRange(addr) => {
+                write!(f, "Fonts at {:#05x} would run past {:#05x}, where programs start", addr, START_ADDR)
+            },
+            Chip8Error::MissingOperand(op) => {
+                write!(f, "Opcode {:#06x} reads its operand from the next word, so it can't be run on its own", op)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
+
+// Errors from loading a ROM into memory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadError {
+    TooLarge { len: usize, max: usize },    // ROM won't fit between its start address and the end of RAM
+    StartOutOfBounds(u16)                   // Start address is past the end of RAM
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::TooLarge { len, max } => {
+                write!(f, "ROM is {} bytes, but at most {} bytes fit in memory", len, max)
+            }
+            LoadError::StartOutOfBounds(start) => {
+                write!(f, "Start address {:#05x} is past the end of memory", start)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+// Something odd about a ROM that still loaded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadWarning {
+    OddLength(usize)                        // Holds the length; instructions are two bytes, so the ROM may be cut short
+}
+
+impl fmt::Display for LoadWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadWarning::OddLength(len) => {
+                write!(f, "ROM is an odd number of bytes long ({}), so it may be truncated", len)
+            }
+        }
+    }
+}
+
+// Why Emu::run or Emu::run_until_halt stopped, as told by last_stop_reason
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    Completed,                      // run got through all the instructions it was asked to
+    BudgetExceeded,                 // run_until_halt ran out of instructions before the program stopped
+    Halted,                         // The program jumped to itself, see is_halted
+    WaitingForKey,                  // An FX0A is waiting for a key, see is_waiting_for_key
+    WaitingForVblank,               // A draw is holding the program until tick_timers (display_wait quirk)
+    UnknownOpcode(u16),             // Paused on this opcode, with set_pause_on_unknown_opcode on
+    Paused,                         // Was paused to begin with, so nothing ran
+    Error(Chip8Error)               // An instruction failed; it was also returned as an error
+}
+
+// How the delay timer counts down
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimerMode {
+    // Once per tick_timers call, so DT reads the same for a whole frame
+    #[default]
+    PerFrame,
+    // Once every ticks_per_frame instructions, so DT moves on between frames'
+    // worth of instructions rather than at the frame boundary
+    PerTick { ticks_per_frame: u32 }
+}
+
+// Length of Emu::rng_state: a 32 byte seed, 8 byte stream, and 16 byte position
+const RNG_STATE_SIZE: usize = 32 + 8 + 16;
+
+// Rebuild a generator from Emu::rng_state, if the state is the right length
+fn rng_from_state(state: &[u8]) -> Option<ChaCha12Rng> {
+    if state.len() != RNG_STATE_SIZE {
+        return None;
+    }
+
+    let seed: [u8; 32] = state[..32].try_into().unwrap();
+    let stream = u64::from_le_bytes(state[32..40].try_into().unwrap());
+    let word_pos = u128::from_le_bytes(state[40..].try_into().unwrap());
+
+    let mut rng = ChaCha12Rng::from_seed(seed);
+    rng.set_stream(stream);
+    rng.set_word_pos(word_pos);
+
+    Some(rng)
+}
+
+// Format version of EmuState. Bump this whenever its fields change, and teach
+// Emu::load_state how to bring older states up to date if that's possible
+pub const STATE_VERSION: u8 = 4;
+
+// Snapshot of the whole machine, taken with Emu::save_state and restored
+// with Emu::load_state. With the `serde` feature it can be written to disk
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EmuState {
+    version: u8,
+    pc: u16,
+    ram: Vec<u8>,
+    screen: Vec<bool>,                              // Each plane's pixels in turn
+    planes: u8,
+    hires: bool,
+    v_reg: [u8; NUM_REGS],
+    i_reg: u16,
+    sp: u16,
+    stack: [u16; STACK_SIZE],
+    keys: [bool; NUM_KEYS],
+    dt: u8,
+    st: u8,
+    audio_pattern: Option<[u8; AUDIO_PATTERN_SIZE]>,
+    pitch: u8,
+    rng: Vec<u8>
+}
+
 // Main class for the emulator
 pub struct Emu {
     pc: u16,                                        // Program counter
-    ram: [u8; RAM_SIZE],                            // RAM, 4KB long
-    screen: [bool; SCREEN_WIDTH * SCREEN_HEIGHT],   // Array of black-and-white pixels
+    start_addr: u16,                                // Where load puts ROMs and reset sends PC
+    ram: Vec<u8>,                                   // RAM, 4KB long unless set with with_memory_size
+    screen: [[bool; SCREEN_BUF_SIZE]; NUM_PLANES],  // Array of black-and-white pixels for each bitplane
+    planes: u8,                                     // Bitplanes that drawing, scrolling, and CLS act on (FN01)
+    hires: bool,                                    // SUPER-CHIP 128x64 mode is on
+    display_dirty: bool,                            // The screen changed since take_display_dirty last looked
     v_reg: [u8; NUM_REGS],                          // V register
     i_reg: u16,                                     // I register
     sp: u16,                                        // Stack pointer
     stack: [u16; STACK_SIZE],                       // Stack
     keys: [bool; NUM_KEYS],                         // Holds the state of each key
     dt: u8,                                         // Delay timer
-    st: u8                                          // Sound timer
+    st: u8,                                         // Sound timer
+    audio_pattern: Option<[u8; AUDIO_PATTERN_SIZE]>, // Sample loop set by F002; None plays the plain beep
+    pitch: u8,                                      // Playback rate of the sample loop (FX3A)
+    quirks: Quirks,                                 // Interpreter-specific behaviors
+    heat: Vec<u32>,                                 // How often the program touched each RAM address
+    stats: InstructionStats,                        // Instructions run of each kind
+    last_op: u16,                                   // Most recently executed opcode
+    paused: bool,                                   // Ticks do nothing while set
+    pause_on_unknown_op: bool,                      // Pause rather than fail on an unknown opcode
+    trap_vf_reads: bool,                            // Fail on 8XY_ instructions that read VF as Vy
+    collided: bool,                                 // A sprite collided since the last tick_timers
+    vblank_wait: bool,                              // A draw is waiting for tick_timers (display_wait quirk)
+    key_wait_held: [bool; NUM_KEYS],                // Keys down when FX0A last checked (wait_for_key_release quirk)
+    key_wait_released: Option<u8>,                  // Latest key let go while FX0A waits (wait_for_key_release quirk)
+    collision_pixels: Vec<(u8, u8)>,                // Pixels erased by sprite collisions since the last tick_timers
+    timer_mode: TimerMode,                          // When the delay timer counts down
+    dt_ticks: u32,                                  // Instructions run since DT last counted down (PerTick mode)
+    idle: bool,                                     // The last instruction left PC where it was
+    halted: bool,                                   // The last instruction was a JP to itself
+    waiting_for_key: bool,                          // The last instruction was an FX0A still waiting
+    last_stop: Option<StopReason>,                  // Why the last run or run_until_halt returned
+    font: [u8; FONTSET_SIZE],                       // Digit sprites installed at font_addr
+    big_font: [u8; BIG_FONTSET_SIZE],               // Big digit sprites installed after the small ones
+    font_addr: u16,                                 // Where the small font starts, with the big font after it
+    rng: ChaCha12Rng,                               // Source of CXNN's random numbers
+    trace: Option<Box<dyn FnMut(u16, u16) + Send>>  // Called with the PC and opcode of each instruction tick runs
 }
 
 pub const START_ADDR: u16 = 0x200;
 
+// RAM below START_ADDR belonged to the interpreter on the original machine.
+// Here it holds just the font
+pub const RESERVED_SIZE: usize = START_ADDR as usize;
+
+impl Default for Emu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Emu {
     pub fn new() -> Self {
+        Self::with_memory_size(RAM_SIZE)
+    }
+
+    // An emulator with more (or less) than the usual 4KB of RAM, e.g. 64KB for
+    // XO-CHIP. Panics unless the size is between RESERVED_SIZE and
+    // MAX_RAM_SIZE, so there's room for the font and every byte can be addressed
+    pub fn with_memory_size(bytes: usize) -> Self {
+        assert!(
+            (RESERVED_SIZE..=MAX_RAM_SIZE).contains(&bytes),
+            "memory size must be between {} and {} bytes, got {}", RESERVED_SIZE, MAX_RAM_SIZE, bytes
+        );
+
         let mut new_emu = Self {
             pc: START_ADDR,
-            ram: [0; RAM_SIZE],
-            screen: [false; SCREEN_WIDTH * SCREEN_HEIGHT],
+            start_addr: START_ADDR,
+            ram: vec![0; bytes],
+            screen: [[false; SCREEN_BUF_SIZE]; NUM_PLANES],
+            planes: 1,
+            hires: false,
+            display_dirty: true,
             v_reg: [0; NUM_REGS],
             i_reg: 0,
             sp: 0,
             stack: [0; STACK_SIZE],
             keys: [false; NUM_KEYS],
             dt: 0,
-            st: 0
+            st: 0,
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            quirks: Quirks::default(),
+            heat: vec![0; bytes],
+            stats: InstructionStats::default(),
+            last_op: 0,
+            paused: false,
+            pause_on_unknown_op: false,
+            trap_vf_reads: false,
+            collided: false,
+            vblank_wait: false,
+            key_wait_held: [false; NUM_KEYS],
+            key_wait_released: None,
+            collision_pixels: Vec::new(),
+            timer_mode: TimerMode::default(),
+            dt_ticks: 0,
+            idle: false,
+            halted: false,
+            waiting_for_key: false,
+            last_stop: None,
+            font: FONTSET,
+            big_font: BIG_FONTSET,
+            font_addr: FONTSET_ADDR,
+            rng: ChaCha12Rng::from_entropy(),
+            trace: None
         };
 
-        new_emu.ram[..FONTSET_SIZE].copy_from_slice(&FONTSET);
+        new_emu.install_fonts();
 
         new_emu
     }
 
-    pub fn get_display(&self) -> &[bool]{
-        &self.screen
+    // An emulator whose CXNN random numbers come out the same every run
+    pub fn with_seed(seed: u64) -> Self {
+        let mut new_emu = Self::new();
+        new_emu.reseed(seed);
+
+        new_emu
     }
 
-    pub fn load(&mut self, data: &[u8]) {
-        let start = START_ADDR as usize;
-        let end = (START_ADDR as usize) + data.len();
-        self.ram[start..end].copy_from_slice(data);
+    // Restart CXNN's random numbers from the given seed, so the same program
+    // with the same input plays out the same way
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = ChaCha12Rng::seed_from_u64(seed);
     }
 
-    pub fn keypress(&mut self, key: usize, pressed: bool){
-        self.keys[key] = pressed;
+    // Everything needed to pick CXNN's random numbers up where they left off:
+    // the generator's seed, stream, and position within the stream
+    pub fn rng_state(&self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(RNG_STATE_SIZE);
+
+        state.extend_from_slice(&self.rng.get_seed());
+        state.extend_from_slice(&self.rng.get_stream().to_le_bytes());
+        state.extend_from_slice(&self.rng.get_word_pos().to_le_bytes());
+
+        state
     }
 
-    pub fn push(&mut self, val: u16){
-        self.stack[self.sp as usize] = val;
-        self.sp += 1;
+    // Restore CXNN's random numbers from what rng_state returned
+    pub fn set_rng_state(&mut self, state: &[u8]) -> Result<(), Chip8Error> {
+        self.rng = rng_from_state(state).ok_or(Chip8Error::InvalidState)?;
+
+        Ok(())
     }
 
-    pub fn pop(&mut self) -> u16 {
-        self.sp -= 1;
-        self.stack[self.sp as usize]
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
     }
 
-    pub fn reset(&mut self){
-        self.pc = START_ADDR;
-        self.ram = [0; RAM_SIZE];
-        self.screen = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
-        self.v_reg = [0; NUM_REGS];
-        self.i_reg = 0;
-        self.sp = 0;
-        self.stack = [0; STACK_SIZE];
-        self.keys = [false; NUM_KEYS];
-        self.dt = 0;
-        self.st = 0;
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
     }
 
-    pub fn tick(&mut self){
-        // Fetch
-        let op = self.fetch();
-        
-        // debug_println!("Executing opcode: {:#06x}", op);
+    // The active quirks as the JSON "options" object Octo uses for its
+    // compatibility settings, so a ROM can be opened in Octo and behave the
+    // same. Octo's flags are written from its own point of view, so several of
+    // ours are inverted. Behaviors we don't make configurable are reported as
+    // what this emulator always does
+    pub fn export_octo_options(&self) -> String {
+        let options = [
+            ("shiftQuirks", !self.quirks.shift_uses_vy),
+            ("loadStoreQuirks", !self.quirks.load_store_increments_i),
+            ("jumpQuirks", self.quirks.jump_uses_vx),
+            ("vfOrderQuirks", false),       // VF is always written after the result
+            ("logicQuirks", false),         // 8XY1/8XY2/8XY3 leave VF alone
+            ("clipQuirks", self.quirks.clip_sprites),
+            ("vBlankQuirks", self.quirks.display_wait)
+        ];
+
+        let fields: Vec<String> = options.iter()
+            .map(|(name, value)| format!("  \"{}\": {}", name, value))
+            .collect();
+
+        format!("{{\n{}\n}}", fields.join(",\n"))
+    }
 
-        // Decode and execute
-        self.execute(op);
+    // Replace the built-in 0-F digit sprites that FX29 points at, for
+    // variants that ship differently shaped glyphs. Each digit is 5 bytes
+    pub fn set_small_font(&mut self, font: [u8; FONTSET_SIZE]) {
+        self.font = font;
+        self.install_fonts();
     }
 
-    fn execute(&mut self, op: u16){
-        let digit1 = (op & 0xF000) >> 12;
-        let digit2 = (op & 0x0F00) >> 8;
-        let digit3 = (op & 0x00F0) >> 4;
-        let digit4 = op & 0x000F;
+    // Replace the built-in SUPER-CHIP 0-F sprites that FX30 points at. Each
+    // digit is 10 bytes
+    pub fn set_big_font(&mut self, font: [u8; BIG_FONTSET_SIZE]) {
+        self.big_font = font;
+        self.install_fonts();
+    }
 
-        match(digit1, digit2, digit3, digit4) {
-            // 0x0000: No operation (NOP)
-            (0,0,0,0) => return,
+    // Move the fonts, e.g. to 0x050 where many SUPER-CHIP interpreters and
+    // assemblers expect them. The small font starts at addr and the big font
+    // follows it; both have to fit below START_ADDR
+    pub fn set_font_address(&mut self, addr: u16) -> Result<(), Chip8Error> {
+        if addr as usize + FONTSET_SIZE + BIG_FONTSET_SIZE > RESERVED_SIZE {
+            return Err(Chip8Error::FontAddressOutOfRange(addr));
+        }
 
-            // 0x00E0: (CLS)
-            // Clear screen 
-            (0,0,0xE,0) => {
-                self.screen = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
-            },
+        let old = self.font_addr as usize;
+        self.ram[old..old + FONTSET_SIZE + BIG_FONTSET_SIZE].fill(0);
 
-            // 0x00EE: (RET)
-            // Return from subroutine
-            (0,0,0xE,0xE) => {
-                // Pop the return address from the stack change the program counter value
-                let ret_addr = self.pop();
-                self.pc = ret_addr;
-            },
+        self.font_addr = addr;
+        self.install_fonts();
 
-            // 0x1NNN: (JP addr)
-            // Jump
-            (1,_,_,_) => {
-                // Get the new address from the bottom three bytes
-                let nnn = op & 0x0FFF;
+        Ok(())
+    }
 
-                // Jump to the new address
-                self.pc = nnn;
-            },
+    // Where the small font starts. The big font is FONTSET_SIZE bytes after it
+    pub fn font_address(&self) -> u16 {
+        self.font_addr
+    }
 
-            // 0x2NNN: (CALL addr)
-            // Call Subroutine 
-            (2,_,_,_) => {
-                self.push(self.pc);
-                self.pc = op & 0x0FFF;
-            },
+    // Copy both fonts into RAM at font_addr
+    fn install_fonts(&mut self) {
+        let small = self.font_addr as usize;
+        let big = small + FONTSET_SIZE;
 
-            // 0x3XNN: (SE Vx, byte)
-            // Skip next if Vx == NN 
-            (3,_,_,_) => {
-                let x = digit2 as usize;
+        self.ram[small..big].copy_from_slice(&self.font);
+        self.ram[big..big + BIG_FONTSET_SIZE].copy_from_slice(&self.big_font);
+    }
 
-                let nn = (op & 0x00FF) as u8;
+    pub fn program_counter(&self) -> u16 {
+        self.pc
+    }
 
-                if self.v_reg[x] == nn {
-                    self.pc += 2;
-                }
-            },
+    // V0 through VF
+    pub fn registers(&self) -> &[u8] {
+        &self.v_reg
+    }
 
-            // 0x4XNN: (SNE Vx, byte)
-            // Skip next if Vx != NN 
-            (4,_,_,_) => {
-                let x = digit2 as usize;
+    pub fn i_register(&self) -> u16 {
+        self.i_reg
+    }
 
-                let nn = (op & 0x00FF) as u8;
+    // Number of return addresses on the stack
+    pub fn stack_pointer(&self) -> u16 {
+        self.sp
+    }
 
-                if self.v_reg[x] != nn {
-                    self.pc += 2;
-                }
-            },
+    pub fn delay_timer(&self) -> u8 {
+        self.dt
+    }
 
-            // 0x5XY0: (SE Vx, Vy)
-            // Skip next if Vx == Vy 
-            (5,_,_,_) => {
-                let x = digit2 as usize;
-                let y = digit2 as usize;
+    pub fn sound_timer(&self) -> u8 {
+        self.st
+    }
 
-                if self.v_reg[x] == self.v_reg[y] {
-                    self.pc += 2;
-                }
-            },
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.dt = value;
+    }
 
-            // 0x6XNN: (LD Vx, byte)
-            // Set Vx = NN 
-            (6,_,_,_) => {
-                let x = digit2 as usize;
-                let nn = (op & 0xFF) as u8;
-                self.v_reg[x] = nn;
-            },
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.st = value;
+    }
 
-            // 0x7XNN: (ADD Vx, byte)
-            // Set Vx += NN 
-            (7,_,_,_) => {
-                let x = digit2 as usize;
-                let nn = (op & 0xFF) as u8;
-                self.v_reg[x] = self.v_reg[x].wrapping_add(nn);
-            },
+    // The pixels of the active display, row by row. Its width and height
+    // depend on the resolution mode, see display_dimensions. This is the
+    // first bitplane, which is all there is unless an XO-CHIP program
+    // selects the second
+    pub fn get_display(&self) -> &[bool]{
+        self.get_plane(0)
+    }
 
-            // 0x8XY0: (LD Vx, Vy)
-            // Set Vx = Vy 
-            (8,_,_,0) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
-                self.v_reg[x] = self.v_reg[y];
-            },
+    // The pixels of one bitplane, 0 or 1, laid out like get_display. A pixel's
+    // color is given by which planes it's lit in. Panics for other planes
+    pub fn get_plane(&self, plane: usize) -> &[bool] {
+        let (width, height) = self.display_dimensions();
+        &self.screen[plane][..width * height]
+    }
 
-            // 0x8XY1: (OR Vx, Vy)
-            // Set Vx = Vx OR Vy 
-            (8,_,_,1) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+    // Bitmask of the planes that drawing and scrolling act on, as last set by
+    // FN01: 1 for the first plane (the default), 2 for the second, 3 for both
+    pub fn selected_planes(&self) -> u8 {
+        self.planes
+    }
 
-                self.v_reg[x] |= self.v_reg[y];
-            },
+    // The (x, y) position of each lit pixel of get_display, row by row, for
+    // frontends that draw points rather than the whole screen
+    pub fn lit_pixels(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let (width, _) = self.display_dimensions();
 
-            // 0x8XY2: (AND Vx, Vy)
-            // Set Vx = Vx AND Vy 
-            (8,_,_,2) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+        self.get_display()
+            .iter()
+            .enumerate()
+            .filter(|(_, pixel)| **pixel)
+            .map(move |(i, _)| (i % width, i / width))
+    }
 
-                self.v_reg[x] &= self.v_reg[y];
-            },
+    // Whether the display has changed since the last call, clearing the flag.
+    // A frontend can skip redrawing frames where nothing changed. It starts
+    // out set, so the first frame is always drawn
+    pub fn take_display_dirty(&mut self) -> bool {
+        let dirty = self.display_dirty;
+        self.display_dirty = false;
 
-            // 0x8XY3: (XOR Vx, Vy)
-            // Set Vx = Vx XOR Vy
-            (8,_,_,3) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+        dirty
+    }
 
-                self.v_reg[x] ^= self.v_reg[y];
-            },
+    // Hand the active display to a frontend's renderer
+    pub fn render<R: Renderer>(&self, renderer: &mut R) {
+        let (width, height) = self.display_dimensions();
+        renderer.draw(self.get_display(), width, height);
+    }
 
-            // 0x8XY4: (ADD Vx, Vy)
-            // Set Vx = Vx + Vy, set VF = carry 
-            (8,_,_,4) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+    // The active display packed eight pixels to a byte, row by row, with the
+    // leftmost pixel in the most significant bit. Both display widths are a
+    // multiple of 8, so every row starts on a fresh byte
+    pub fn packed_display(&self) -> Vec<u8> {
+        self.get_display()
+            .chunks(8)
+            .map(|pixels| pixels.iter().fold(0, |byte, pixel| (byte << 1) | *pixel as u8))
+            .collect()
+    }
 
-                let (sum, carry) = self.v_reg[x].overflowing_add(self.v_reg[y]);
-                
-                self.v_reg[x] = sum;
-                self.v_reg[0xF] = carry as u8;
-            },
+    // Width and height of the display in pixels
+    pub fn display_dimensions(&self) -> (usize, usize) {
+        if self.hires {
+            (HIRES_SCREEN_WIDTH, HIRES_SCREEN_HEIGHT)
+        } else {
+            (SCREEN_WIDTH, SCREEN_HEIGHT)
+        }
+    }
 
-            // 0x8XY5: (SUB Vx, Vy)
-            // Set Vx = Vy - Vy, set VF = NOT borrow 
-            (8,_,_,5) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+    // Whether the SUPER-CHIP 128x64 display mode is on
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
 
-                let not_borrow: bool = self.v_reg[x] > self.v_reg[y];
-                let difference = self.v_reg[x].wrapping_sub(self.v_reg[y]);
+    // Whether the pixel at (x, y) is lit. Coordinates off the screen are never lit
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        let (width, height) = self.display_dimensions();
 
-                self.v_reg[x] = difference;
-                self.v_reg[0xF] = not_borrow as u8;
-            },
+        if x >= width || y >= height {
+            return false;
+        }
 
-            // 0x8XY6: (SHR Vx {, Vy})
-            // SHR = Single right-shift
-            // Set Vx = Vx >> 1, VF = LSB before shift
-            (8,_,_,6) => {
-                let x = digit2 as usize;
+        self.screen[0][x + width * y]
+    }
 
-                let lsb = self.v_reg[x] & 0x01;
+    // Fill the screen with a one-pixel border and a crosshair through the center,
+    // for checking a frontend's alignment, scaling, and colors without a ROM
+    pub fn load_test_pattern(&mut self) {
+        let (width, height) = self.display_dimensions();
+        let mid_x = width / 2;
+        let mid_y = height / 2;
 
-                self.v_reg[x] >>= 1;
-                self.v_reg[0xF] = lsb;
-            },
+        for y in 0..height {
+            for x in 0..width {
+                let border = x == 0 || y == 0 || x == width - 1 || y == height - 1;
+                let crosshair = x == mid_x || y == mid_y;
 
-            // 0x8XY7: (SUBN Vx, Vy)
-            // Set Vx = Vy - Vx, Set VF = NOT borrow
-            (8,_,_,7) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+                self.screen[0][x + width * y] = border || crosshair;
+            }
+        }
 
-                let not_borrow: bool = self.v_reg[y] > self.v_reg[x];
+        self.display_dirty = true;
+    }
 
-                self.v_reg[x] = self.v_reg[y].wrapping_sub(self.v_reg[x]);
-                self.v_reg[0xF] = not_borrow as u8;
-            },
+    // Render the screen as Unicode Braille, one character per 2x4 block of
+    // pixels, giving a 32x8 character picture of the 64x32 display (or 64x16
+    // in high resolution)
+    pub fn display_braille(&self) -> String {
+        // Bit for each dot in a Braille cell, indexed by [row][column].
+        // Dots 1-6 fill the top three rows, dots 7 and 8 the bottom row
+        const DOT_BITS: [[u32; 2]; 4] = [
+            [0x01, 0x08],
+            [0x02, 0x10],
+            [0x04, 0x20],
+            [0x40, 0x80]
+        ];
+
+        let (width, height) = self.display_dimensions();
+        let mut out = String::new();
+
+        for cell_y in 0..height / 4 {
+            for cell_x in 0..width / 2 {
+                let mut bits = 0;
+
+                for (dy, row) in DOT_BITS.iter().enumerate() {
+                    for (dx, bit) in row.iter().enumerate() {
+                        let x = cell_x * 2 + dx;
+                        let y = cell_y * 4 + dy;
+
+                        if self.screen[0][x + width * y] {
+                            bits |= bit;
+                        }
+                    }
+                }
 
-            // 0x8XYE: (SHL Vx {, Vy})
-            // Set Vx = Vx << 1, VF = MSB before shift
-            (8,_,_,0xE) => {
-                let x = digit2 as usize;
+                // Every combination of dots is a valid character in the Braille block
+                out.push(char::from_u32(0x2800 + bits).unwrap());
+            }
 
-                let msb: u8 = (self.v_reg[x] >> 7) & 0x01;
+            out.push('\n');
+        }
 
-                self.v_reg[x] <<= 1;
-                self.v_reg[0xF] = msb;
-            },
+        out
+    }
 
-            // 0x9XY0: (SNE Vx, Vy)
-            // Skip next instruction if Vx != Vy
-            (9,_,_,0) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+    // Measure how much the screen flickers over a sequence of captured frames.
+    // Returns the fraction of pixels that toggled between consecutive frames,
+    // from 0.0 (perfectly stable) to 1.0 (every pixel flipped every frame)
+    pub fn flicker_metric(&self, frames: &[Vec<bool>]) -> f32 {
+        if frames.len() < 2 {
+            return 0.0;
+        }
 
-                if self.v_reg[x] != self.v_reg[y] {
-                    self.pc += 2;
-                }
-            },
+        let num_pixels = self.get_display().len();
+        let mut toggles = 0;
 
-            // 0xANNN: (LD I, addr)
-            // Set I = NNN
-            (0xA,_,_,_) => {
-                self.i_reg = op & 0x0FFF;
-            },
+        for pair in frames.windows(2) {
+            for i in 0..num_pixels {
+                let before = pair[0].get(i).copied().unwrap_or(false);
+                let after = pair[1].get(i).copied().unwrap_or(false);
 
-            // 0xBNNN: (JP V0, addr)
-            // Jump to location NNN + V0
-            (0xB,_,_,_) => {
-                self.pc = self.v_reg[0] as u16 + (op & 0x0FFF);
-            },
+                if before != after {
+                    toggles += 1;
+                }
+            }
+        }
 
-            // 0xCXNN: RND Vx, byte
-            // Set Vx = random byte AND NN
-            (0xC,_,_,_) => {
-                let x = digit2 as usize;
-                
-                let mut rng = rand::thread_rng();
-                let rand: u8 = rng.gen();
+        toggles as f32 / ((frames.len() - 1) * num_pixels) as f32
+    }
 
-                self.v_reg[x] = rand & (op & 0x00FF) as u8;
-            },
+    pub fn save_state(&self) -> EmuState {
+        EmuState {
+            version: STATE_VERSION,
+            pc: self.pc,
+            ram: self.ram.to_vec(),
+            screen: self.screen.concat(),
+            planes: self.planes,
+            hires: self.hires,
+            v_reg: self.v_reg,
+            i_reg: self.i_reg,
+            sp: self.sp,
+            stack: self.stack,
+            keys: self.keys,
+            dt: self.dt,
+            st: self.st,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
+            rng: self.rng_state()
+        }
+    }
 
-            // 0xDXYN: DRW Vx, Vy, nibble
-            // Display n-byte sprite starting at memory location I at (Vx, Vy), set VF = collision
-            (0xD,_,_,_) => {
-                let x_coord = self.v_reg[digit2 as usize] as u16;
-                let y_coord = self.v_reg[digit3 as usize] as u16;
+    pub fn load_state(&mut self, state: &EmuState) -> Result<(), Chip8Error> {
+        // Older formats get converted here once there are any. For now the
+        // current version is the only one we understand
+        match state.version {
+            STATE_VERSION => {},
+            found => return Err(Chip8Error::IncompatibleStateVersion { found, expected: STATE_VERSION })
+        }
 
-                // The last digit determines how many rows high our sprite is
-                let num_rows = digit4;
-                
-                // Keep track if any pixels were flipped
-                let mut flipped = false;
-                
-                // Iterate over each row of our sprite
-                for y_line in 0..num_rows {
-                    // Determine which memory address our row's data is stored
-                    let addr = self.i_reg + y_line as u16;
-                    let pixels = self.ram[addr as usize];
-
-                    // Iterate over each column in our row
-                    for x_line in 0..8 {
-                        // Use a mask to fetch current pixel's bit. Only flip if a 1
-                        if (pixels & (0b1000_0000 >> x_line)) != 0 {
-                            // Sprites should wrap around screen, so apply modulo
-                            let x = (x_coord + x_line) as usize % SCREEN_WIDTH;
-                            let y = (y_coord + y_line) as usize % SCREEN_HEIGHT;
-
-                            // Get our pixel's index for our 1D screen array
-                            let idx = x + SCREEN_WIDTH * y;
-
-                            // Check if we're about to flip the pixel and set
-                            flipped |= self.screen[idx];
-                            self.screen[idx] ^= true;
-                        }
-                    }
-                }
-                // Populate VF register
-                if flipped {
-                    self.v_reg[0xF] = 1;
-                } else {
-                    self.v_reg[0xF] = 0;
-                }
+        // A state read from disk could have been tampered with, so check it
+        // fits before touching anything
+        let rng = rng_from_state(&state.rng);
+        if state.ram.len() != self.ram.len()
+            || state.screen.len() != NUM_PLANES * SCREEN_BUF_SIZE
+            || state.sp as usize > STACK_SIZE
+            || rng.is_none() {
+            return Err(Chip8Error::InvalidState);
+        }
 
-            },
+        self.pc = state.pc;
+        self.ram.copy_from_slice(&state.ram);
+        for (plane, pixels) in self.screen.iter_mut().zip(state.screen.chunks_exact(SCREEN_BUF_SIZE)) {
+            plane.copy_from_slice(pixels);
+        }
+        self.planes = state.planes & 0x3;
+        self.hires = state.hires;
+        self.display_dirty = true;
+        self.v_reg = state.v_reg;
+        self.i_reg = state.i_reg;
+        self.sp = state.sp;
+        self.stack = state.stack;
+        self.keys = state.keys;
+        self.key_wait_held = [false; NUM_KEYS];
+        self.key_wait_released = None;
+        self.halted = false;
+        self.waiting_for_key = false;
+        self.dt = state.dt;
+        self.st = state.st;
+        self.audio_pattern = state.audio_pattern;
+        self.pitch = state.pitch;
+        self.rng = rng.unwrap();
+
+        Ok(())
+    }
 
-            // 0xEX9E: SKP Vx
-            // Skip next instruction if key with the value of Vx is pressed
-            (0xE,_,0x9,0xE) => {
-                let x = digit2 as usize;
+    // A hash of everything that determines what the machine does next: PC,
+    // registers, stack, timers, screen, and RAM. Two emulators that agree
+    // after every step will produce identical hashes
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = Fnv1a::new();
 
-                let key_no = self.v_reg[x] as usize;
+        self.hash_machine(&mut hasher);
+        hasher.write(&self.ram);
 
-                if self.keys[key_no] {
-                    self.pc += 2;
-                }
-            },
+        hasher.finish()
+    }
 
-            // 0xEXA1: SKNP Vx
-            // Skip next instruction if key with the value of Vx is not pressed.
-            (0xE,_,0xA,0x1) => {
-                let x = digit2 as usize;
+    // A hash of just what's on screen: the resolution and the active
+    // display's pixels in each plane. Stable across platforms and Rust
+    // versions, so a test can run a ROM headlessly and compare the frame it
+    // ends on with a pinned value instead of a whole bitmap
+    pub fn display_hash(&self) -> u64 {
+        let mut hasher = Fnv1a::new();
+        let (width, height) = self.display_dimensions();
+
+        hasher.write(&[width as u8, height as u8]);
+        for plane in 0..NUM_PLANES {
+            for pixel in self.get_plane(plane) {
+                hasher.write(&[*pixel as u8]);
+            }
+        }
 
-                let key_no = self.v_reg[x] as usize;
+        hasher.finish()
+    }
 
-                if !self.keys[key_no] {
-                    self.pc += 2;
-                }
-            },
+    // Like state_hash but leaving out RAM, for comparing machines that were
+    // loaded with different ROMs
+    pub(crate) fn machine_hash(&self) -> u64 {
+        let mut hasher = Fnv1a::new();
+        self.hash_machine(&mut hasher);
 
-            // 0xFX07: LD Vx, DT
-            // Set Vx = delay timer value.
-            (0xF,_,0x0,0x7) => {
-                let x = digit2 as usize;
+        hasher.finish()
+    }
 
-                self.v_reg[x] = self.dt;
-            },
+    fn hash_machine(&self, hasher: &mut Fnv1a) {
+        hasher.write(&self.pc.to_be_bytes());
+        hasher.write(&self.v_reg);
+        hasher.write(&self.i_reg.to_be_bytes());
+        hasher.write(&self.sp.to_be_bytes());
+        for entry in self.stack.iter() {
+            hasher.write(&entry.to_be_bytes());
+        }
+        hasher.write(&[self.dt, self.st]);
+        hasher.write(&[self.hires as u8, self.planes]);
+        for pixel in self.screen.iter().flatten() {
+            hasher.write(&[*pixel as u8]);
+        }
+    }
 
-            // 0xFX0A: LD Vx, K
-            // Wait for a key press, store the value of the key in Vx.
-            (0xF,_,0x0,0xA) => {
-                let x = digit2 as usize;
+    // Read a byte of RAM, or None if the address is past the end of it. Unlike
+    // the program's own reads, these don't count towards access_heat
+    pub fn read_ram(&self, addr: u16) -> Option<u8> {
+        self.ram.get(addr as usize).copied()
+    }
 
-                // Initialize this to an invalid number initially
-                let mut key_no = NUM_KEYS as u8;
+    // Overwrite a byte of RAM, for memory editors, cheats, and test setup
+    pub fn write_ram(&mut self, addr: u16, val: u8) -> Result<(), Chip8Error> {
+        let byte = self.ram.get_mut(addr as usize).ok_or(Chip8Error::AddressOutOfBounds(addr))?;
+        *byte = val;
 
-                // Check if any of the keys have been pressed
-                for i in 0..NUM_KEYS {
-                    if self.keys[i] {
-                        key_no = i as u8;
-                    }
-                }
+        Ok(())
+    }
 
-                // If none have been pressed, repeat the same instruction
-                if key_no == NUM_KEYS as u8 {
-                    self.pc -= 2;
-                } else {
-                    self.v_reg[x] = key_no;
-                }
-            },
+    // The interpreter's area of RAM below START_ADDR, including the font
+    pub fn reserved_region(&self) -> &[u8] {
+        &self.ram[..RESERVED_SIZE]
+    }
 
-            // 0xFX15: LD DT, Vx
-            // Set delay timer = Vx
-            (0xF,_,0x1,0x5) => {
-                let x = digit2 as usize;
+    // Number of times the running program has read or written each RAM
+    // address, saturating at u32::MAX. Useful for a memory heatmap
+    pub fn access_heat(&self) -> &[u32] {
+        &self.heat
+    }
 
-                self.dt = self.v_reg[x];
-            },
+    // Counts of the instructions run since the emulator was created, reset,
+    // or had reset_stats called
+    pub fn instruction_stats(&self) -> &InstructionStats {
+        &self.stats
+    }
 
-            // 0xFX18: LD ST, Vx
-            // Set sound timer = Vx
-            (0xF,_,0x1,0x8) => {
-                let x = digit2 as usize;
+    pub fn reset_stats(&mut self) {
+        self.stats.clear();
+    }
 
-                self.st = self.v_reg[x];
-            },
+    // Load a ROM at the start address (START_ADDR unless set with
+    // EmuBuilder::start_address) and begin running from there. A ROM that
+    // loads but looks suspect comes back with a warning
+    pub fn load(&mut self, data: &[u8]) -> Result<Option<LoadWarning>, LoadError> {
+        self.load_at(data, self.start_addr)
+    }
 
-            // 0xFX1E: ADD I, Vx
-            // Set I = I + Vx
-            (0xF,_,0x1,0xE) => {
-                let x = digit2 as usize;
+    // Where load puts ROMs and reset sends PC
+    pub fn start_address(&self) -> u16 {
+        self.start_addr
+    }
 
-                self.i_reg = self.i_reg.wrapping_add(self.v_reg[x] as u16);
-            },
+    // Load a ROM at a start address other than the usual 0x200 and begin
+    // running from there, for variants such as the ETI-660 that use 0x600
+    pub fn load_at(&mut self, data: &[u8], start: u16) -> Result<Option<LoadWarning>, LoadError> {
+        if start as usize >= self.ram.len() {
+            return Err(LoadError::StartOutOfBounds(start));
+        }
 
-            // 0xFX29: LD F, Vx
-            // Set I = location of sprite for digit Vx
-            (0xF,_,0x2,0x9) => {
-                let x = digit2 as usize;
-                let c = self.v_reg[x] as u16;
+        let start = start as usize;
+        let max = self.ram.len() - start;
 
-                self.i_reg = FONTSET_ADDR as u16 + (FONT_SIZE as u16 * c);
-            },
+        if data.len() > max {
+            return Err(LoadError::TooLarge { len: data.len(), max });
+        }
 
-            // 0xFX33: LD B, Vx
-            // Store BCD representation of Vx in memory locations I, I+1, and I+2.
-            (0xF,_,0x3,0x3) => {
-                let x = digit2 as usize;
+        let end = start + data.len();
+        self.ram[start..end].copy_from_slice(data);
+        self.pc = start as u16;
+        self.halted = false;
+        self.waiting_for_key = false;
 
-                let hundreds = (x / 100) as u8;
-                let tens = ((x % 100) / 10) as u8;
-                let ones = (x % 10) as u8;
+        if data.len() % 2 == 1 {
+            return Ok(Some(LoadWarning::OddLength(data.len())));
+        }
 
-                let start_addr = self.i_reg as usize;
+        Ok(None)
+    }
 
-                self.ram[start_addr] = hundreds;
-                self.ram[start_addr + 1] = tens;
-                self.ram[start_addr + 2] = ones;
-            },
+    // Press or release a key. Keys past the end of the keypad are ignored
+    pub fn keypress(&mut self, key: usize, pressed: bool){
+        if let Some(state) = self.keys.get_mut(key) {
+            *state = pressed;
+        }
+    }
 
-            // 0xFX55: LD [I], Vx
-            // Store registers V0 through Vx in memory starting at location I.
-            (0xF,_,0x5,0x5) => {
-                let x = digit2 as usize;
-                let start_addr = self.i_reg as usize;
+    // Press or release a key
+    pub fn set_key(&mut self, key: Key, pressed: bool) {
+        self.keys[key as usize] = pressed;
+    }
 
-                for i in 0..x {
-                    self.ram[start_addr + i] = self.v_reg[i];
-                }
-            },
+    // Whether a key is held down. Keys past the end of the keypad never are
+    pub fn key_state(&self, key: usize) -> bool {
+        self.keys.get(key).copied().unwrap_or(false)
+    }
 
-            // 0xFX65: LD Vx, [I]
-            // Read registers V0 through Vx from memory starting at location I.
-            (0xF,_,0x6,0x5) => {
-                let x = digit2 as usize;
-                let start_addr = self.i_reg as usize;
+    // The pressed/released state of every key on the keypad
+    pub fn keypad(&self) -> &[bool; NUM_KEYS] {
+        &self.keys
+    }
 
-                for i in 0..x {
-                    self.v_reg[i] = self.ram[start_addr + i];
-                }
+    // Replace the state of the whole keypad at once
+    pub fn set_key_state(&mut self, keys: &[bool; NUM_KEYS]) {
+        self.keys = *keys;
+    }
+
+    pub fn push(&mut self, val: u16) -> Result<(), Chip8Error> {
+        if self.sp as usize >= STACK_SIZE {
+            match self.quirks.stack_overflow {
+                StackOverflowPolicy::Error => return Err(Chip8Error::StackOverflow),
+                StackOverflowPolicy::Saturate => return Ok(()),
+                StackOverflowPolicy::Wrap => self.sp = 0
             }
+        }
+
+        self.stack[self.sp as usize] = val;
+        self.sp += 1;
 
+        Ok(())
+    }
 
-            (_,_,_,_) => unimplemented!("Unimplented opcode: {:#04x}", op)
+    pub fn pop(&mut self) -> Result<u16, Chip8Error> {
+        if self.sp == 0 {
+            return Err(Chip8Error::StackUnderflow);
         }
+
+        self.sp -= 1;
+        Ok(self.stack[self.sp as usize])
     }
 
-    fn fetch(&mut self) -> u16 {
-        let higher_byte = self.ram[(self.pc) as usize] as u16;
-        let lower_byte = self.ram[(self.pc + 1) as usize] as u16;
+    // Every stack slot, along with the stack pointer
+    pub fn export_stack(&self) -> (Vec<u16>, u16) {
+        (self.stack.to_vec(), self.sp)
+    }
 
-        let op = (higher_byte << 8) | lower_byte;
-        self.pc += 2;
+    // Replace the stack with the given entries (any slots past them are
+    // zeroed) and stack pointer
+    pub fn import_stack(&mut self, entries: &[u16], sp: u16) -> Result<(), Chip8Error> {
+        if entries.len() > STACK_SIZE || sp as usize > STACK_SIZE {
+            return Err(Chip8Error::InvalidStack);
+        }
 
-        op
+        self.stack = [0; STACK_SIZE];
+        self.stack[..entries.len()].copy_from_slice(entries);
+        self.sp = sp;
+
+        Ok(())
     }
 
-    pub fn tick_timers(&mut self){
-        if self.dt > 0 {
-            self.dt -= 1;
+    pub fn reset(&mut self){
+        self.pc = self.start_addr;
+        self.ram.fill(0);
+        self.screen = [[false; SCREEN_BUF_SIZE]; NUM_PLANES];
+        self.planes = 1;
+        self.display_dirty = true;
+        self.hires = false;
+        self.v_reg = [0; NUM_REGS];
+        self.i_reg = 0;
+        self.sp = 0;
+        self.stack = [0; STACK_SIZE];
+        self.keys = [false; NUM_KEYS];
+        self.dt = 0;
+        self.st = 0;
+        self.audio_pattern = None;
+        self.pitch = DEFAULT_PITCH;
+        self.heat.fill(0);
+        self.stats.clear();
+        self.last_op = 0;
+        self.paused = false;
+        self.collided = false;
+        self.collision_pixels.clear();
+        self.vblank_wait = false;
+        self.key_wait_held = [false; NUM_KEYS];
+        self.key_wait_released = None;
+        self.dt_ticks = 0;
+        self.idle = false;
+        self.halted = false;
+        self.waiting_for_key = false;
+        self.last_stop = None;
+
+        // Clearing RAM took the digit sprites with it, so put them back for FX29 and FX30
+        self.install_fonts();
+    }
+
+    // Reset the machine and load a new ROM into it in one go
+    pub fn reset_and_load(&mut self, rom: &[u8]) -> Result<Option<LoadWarning>, LoadError> {
+        self.reset();
+        self.load(rom)
+    }
+
+    // When enabled, an unknown opcode pauses the emulator with PC still
+    // pointing at it, instead of stopping emulation altogether. Handy for
+    // inspecting the machine at the point a ROM in development goes wrong
+    pub fn set_pause_on_unknown_opcode(&mut self, enabled: bool) {
+        self.pause_on_unknown_op = enabled;
+    }
+
+    // When enabled, an 8XY_ instruction that takes VF as its Vy operand stops
+    // with Chip8Error::VfRead, leaving PC pointing at it. VF is overwritten by
+    // so many instructions that using it as an ordinary value is almost always
+    // a mistake. Testing it with 3FNN/4FNN is left alone, as that's how flags
+    // are meant to be read
+    pub fn set_trap_vf_reads(&mut self, enabled: bool) {
+        self.trap_vf_reads = enabled;
+    }
+
+    // Have tick call cb with the address and opcode of every instruction it
+    // fetches, just before running it. Pass None to stop tracing. The callback
+    // has to be Send so the emulator can still move between threads
+    pub fn set_trace_callback(&mut self, cb: Option<Box<dyn FnMut(u16, u16) + Send>>) {
+        self.trace = cb;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    // The opcode of the most recently executed instruction. After pausing on an
+    // unknown opcode, this is the offending opcode
+    pub fn last_opcode(&self) -> u16 {
+        self.last_op
+    }
+
+    // The opcode at PC, which the next tick will execute. None if PC is too
+    // close to the end of RAM for a whole instruction. Doesn't count towards
+    // access_heat
+    pub fn peek_opcode(&self) -> Option<u16> {
+        let pc = self.pc as usize;
+
+        if pc + 1 >= self.ram.len() {
+            return None;
         }
 
-        if self.st > 0 {
-            if self.st == 1 {
-                // BEEP
-            }
+        Some(((self.ram[pc] as u16) << 8) | self.ram[pc + 1] as u16)
+    }
 
-            self.st -= 1;
+    pub fn tick(&mut self) -> Result<(), Chip8Error> {
+        self.step().map(|_| ())
+    }
+
+    // Run the instruction at PC and return its opcode, or None if paused or
+    // waiting for the next frame. Everything that runs the program's own
+    // instructions goes through here
+    fn step(&mut self) -> Result<Option<u16>, Chip8Error> {
+        if self.paused || self.vblank_wait {
+            return Ok(None);
+        }
+
+        let start_pc = self.pc;
+
+        // Fetch
+        let op = self.fetch()?;
+        
+        // debug_println!("Executing opcode: {:#06x}", op);
+
+        if let Some(trace) = &mut self.trace {
+            trace(start_pc, op);
+        }
+
+        // Decode and execute
+        self.execute(op)?;
+
+        self.idle = self.pc == start_pc;
+
+        if let TimerMode::PerTick { ticks_per_frame } = self.timer_mode {
+            self.dt_ticks += 1;
+
+            if self.dt_ticks >= ticks_per_frame.max(1) {
+                self.dt_ticks = 0;
+                self.dt = self.dt.saturating_sub(1);
+            }
         }
+
+        Ok(Some(op))
+    }
+
+    // Whether the last instruction left PC where it was, as a jump to itself
+    // or a wait for a keypress does. A program in this state is spinning
+    // without doing anything until a timer runs out or a key is pressed
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    // Whether the program has stopped for good by jumping to the instruction
+    // it's on. Unlike is_idle, this isn't set while waiting for a key or
+    // a timer, which the program can come out of
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    // Whether the last instruction was an FX0A that's still waiting for a key.
+    // Without a key, the program won't get any further however long it runs,
+    // so a headless runner can use this to press one or give up
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.waiting_for_key
+    }
+
+    pub fn set_timer_mode(&mut self, mode: TimerMode) {
+        self.timer_mode = mode;
+        self.dt_ticks = 0;
+        self.idle = false;
+    }
+
+    // Run the given number of instructions without touching the frame timers,
+    // for driving the emulator headlessly. Stops early if the emulator pauses
+    // or a draw waits for the next frame, since no more would run; see
+    // last_stop_reason for which. With the display_wait quirk, that means it
+    // stops after the first DXYN, and the caller has to call tick_timers
+    // before running again
+    pub fn run(&mut self, ticks: usize) -> Result<(), Chip8Error> {
+        self.run_for(ticks, false).map(|_| ())
+    }
+
+    // Run until the program halts or waits for a key, or max_ticks
+    // instructions have run, and say which. Like run, this also stops if the
+    // emulator pauses or a draw waits for the next frame, so with the
+    // display_wait quirk it returns WaitingForVblank after the first DXYN
+    // until tick_timers is called
+    pub fn run_until_halt(&mut self, max_ticks: usize) -> Result<StopReason, Chip8Error> {
+        self.run_for(max_ticks, true)
+    }
+
+    // Why the last call to run or run_until_halt returned, including the
+    // error if it failed. None if neither has run since the emulator was
+    // created or reset
+    pub fn last_stop_reason(&self) -> Option<StopReason> {
+        self.last_stop
     }
 
-}
\ No newline at end of file
+    // The loop behind run and run_until_halt, which also stops once the
+    // program halts or waits for a key if until_halt is set
+    fn run_for(&mut self, max_ticks: usize, until_halt: bool) -> Result<StopReason, Chip8Error> {
+        let result = self.run_steps(max_ticks, until_halt);
+
+        self.last_stop = Some(match result {
+            Ok(reason) => reason,
+            Err(e) => StopReason::Error(e)
+        });
+
+        result
+    }
+
+    fn run_steps(&mut self, max_ticks: usize, until_halt: bool) -> Result<StopReason, Chip8Error> {
+        for _ in 0..max_ticks {
+            if self.paused {
+                return Ok(StopReason::Paused);
+            }
+            if self.vblank_wait {
+                return Ok(StopReason::WaitingForVblank);
+            }
+
+            self.step()?;
+
+            // Only an unknown opcode pauses the emulator from inside
+            if self.paused {
+                return Ok(StopReason::UnknownOpcode(self.last_op));
+            }
+
+            if until_halt && self.halted {
+                return Ok(StopReason::Halted);
+            }
+            if until_halt && self.waiting_for_key {
+                return Ok(StopReason::WaitingForKey);
+            }
+        }
+
+        if until_halt {
+            Ok(StopReason::BudgetExceeded)
+        } else {
+            Ok(StopReason::Completed)
+        }
+    }
+
+    // Run a single opcode as if it had just been fetched from the address
+    // before PC, without fetching it from RAM. Jumps, calls, and returns set
+    // PC as usual and skips move it past the instruction at PC, so a test can
+    // set up registers, call execute_opcode(0x8124), and check the result.
+    // F000 is refused with MissingOperand, since its address is the word it
+    // would have to fetch after it
+    pub fn execute_opcode(&mut self, op: u16) -> Result<(), Chip8Error> {
+        if op == 0xF000 {
+            return Err(Chip8Error::MissingOperand(op));
+        }
+
+        self.execute(op)
+    }
+
+    // Run instructions until one that isn't a 0x0000 NOP executes, and
+    // return that opcode. Useful for stepping through padded ROMs. Each
+    // instruction, NOPs included, is run just as tick runs it, so like tick
+    // this does nothing and returns None while paused or waiting for the
+    // next frame
+    pub fn step_skip_nops(&mut self) -> Result<Option<u16>, Chip8Error> {
+        // RAM can't hold more instructions than this, so passing over this
+        // many NOPs means we're looping
+        let max_nops = self.ram.len() / 2;
+
+        for _ in 0..max_nops {
+            match self.step()? {
+                Some(0x0000) => (),
+                op => return Ok(op)
+            }
+        }
+
+        Err(Chip8Error::NopLimitExceeded(self.pc))
+    }
+
+    fn execute(&mut self, op: u16) -> Result<(), Chip8Error> {
+        self.last_op = op;
+        self.halted = false;
+        self.waiting_for_key = false;
+
+        let digit1 = (op & 0xF000) >> 12;
+        let digit2 = (op & 0x0F00) >> 8;
+        let digit3 = (op & 0x00F0) >> 4;
+        let digit4 = op & 0x000F;
+
+        if self.trap_vf_reads && digit1 == 8 && digit3 == 0xF {
+            // Shifts only read Vy when the quirk says so
+            let reads_vy = match digit4 {
+                0..=5 | 7 => true,
+                6 | 0xE => self.quirks.shift_uses_vy,
+                _ => false
+            };
+
+            if reads_vy {
+                self.pc = self.pc.wrapping_sub(2);
+                return Err(Chip8Error::VfRead(op));
+            }
+        }
+
+        self.stats.record(op);
+
+        match(digit1, digit2, digit3, digit4) {
+            // 0x0___: screen and subroutine control, and calls into machine code
+            (0,_,_,_) => self.execute_system(op)?,
+
+            // 0x1NNN: (JP addr)
+            // Jump
+            (1,_,_,_) => {
+                // Get the new address from the bottom three bytes
+                let nnn = op & 0x0FFF;
+
+                // A jump to itself is how most programs end; nothing
+                // but a reset or a loaded state gets them out of it
+                self.halted = nnn == self.pc.wrapping_sub(2);
+
+                // Jump to the new address
+                self.pc = nnn;
+            },
+
+            // 0x2NNN: (CALL addr)
+            // Call Subroutine 
+            (2,_,_,_) => {
+                self.push(self.pc)?;
+                self.pc = op & 0x0FFF;
+            },
+
+            // 0x3XNN: (SE Vx, byte)
+            // Skip next if Vx == NN 
+            (3,_,_,_) => {
+                let x = digit2 as usize;
+
+                let nn = (op & 0x00FF) as u8;
+
+                if self.v_reg[x] == nn {
+                    self.skip_next_instruction();
+                }
+            },
+
+            // 0x4XNN: (SNE Vx, byte)
+            // Skip next if Vx != NN 
+            (4,_,_,_) => {
+                let x = digit2 as usize;
+
+                let nn = (op & 0x00FF) as u8;
+
+                if self.v_reg[x] != nn {
+                    self.skip_next_instruction();
+                }
+            },
+
+            // 0x5XY0: (SE Vx, Vy)
+            // Skip next if Vx == Vy 
+            (5,_,_,0) => {
+                let x = digit2 as usize;
+                let y = digit3 as usize;
+
+                if self.v_reg[x] == self.v_reg[y] {
+                    self.skip_next_instruction();
+                }
+            },
+
+            // 0x6XNN: (LD Vx, byte)
+            // Set Vx = NN 
+            (6,_,_,_) => {
+                let x = digit2 as usize;
+                let nn = (op & 0xFF) as u8;
+                self.v_reg[x] = nn;
+            },
+
+            // 0x7XNN: (ADD Vx, byte)
+            // Set Vx += NN 
+            (7,_,_,_) => {
+                let x = digit2 as usize;
+                let nn = (op & 0xFF) as u8;
+
+                let (sum, carry) = self.v_reg[x].overflowing_add(nn);
+                self.v_reg[x] = sum;
+
+                // The spec leaves VF alone here, but some interpreters treat it like 8XY4
+                if self.quirks.add_imm_sets_vf {
+                    self.v_reg[0xF] = carry as u8;
+                }
+            },
+
+            // The 8XY_ instructions that set a flag all read Vx and Vy before
+            // writing anything, then write the result to Vx and the flag to VF
+            // last, as the VIP did. So when VF is an operand its old value is
+            // the one used, and when Vx is VF the flag overwrites the result:
+            // 8FF4 with VF = 0x80 leaves VF = 1, and 8F05 leaves VF as the
+            // borrow flag, not the difference
+
+            // 0x8XY0: (LD Vx, Vy)
+            // Set Vx = Vy
+            (8,_,_,0) => {
+                let x = digit2 as usize;
+                let y = digit3 as usize;
+                self.v_reg[x] = self.v_reg[y];
+            },
+
+            // 0x8XY1: (OR Vx, Vy)
+            // Set Vx = Vx OR Vy 
+            (8,_,_,1) => {
+                let x = digit2 as usize;
+                let y = digit3 as usize;
+
+                self.v_reg[x] |= self.v_reg[y];
+            },
+
+            // 0x8XY2: (AND Vx, Vy)
+            // Set Vx = Vx AND Vy 
+            (8,_,_,2) => {
+                let x = digit2 as usize;
+                let y = digit3 as usize;
+
+                self.v_reg[x] &= self.v_reg[y];
+            },
+
+            // 0x8XY3: (XOR Vx, Vy)
+            // Set Vx = Vx XOR Vy
+            (8,_,_,3) => {
+                let x = digit2 as usize;
+                let y = digit3 as usize;
+
+                self.v_reg[x] ^= self.v_reg[y];
+            },
+
+            // 0x8XY4: (ADD Vx, Vy)
+            // Set Vx = Vx + Vy, set VF = carry 
+            (8,_,_,4) => {
+                let x = digit2 as usize;
+                let y = digit3 as usize;
+
+                let (sum, carry) = self.v_reg[x].overflowing_add(self.v_reg[y]);
+                
+                self.v_reg[x] = sum;
+                self.v_reg[0xF] = carry as u8;
+            },
+
+            // 0x8XY5: (SUB Vx, Vy)
+            // Set Vx = Vx - Vy, set VF = NOT borrow 
+            (8,_,_,5) => {
+                let x = digit2 as usize;
+                let y = digit3 as usize;
+
+                let not_borrow: bool = self.v_reg[x] >= self.v_reg[y];
+                let difference = self.v_reg[x].wrapping_sub(self.v_reg[y]);
+
+                self.v_reg[x] = difference;
+                self.v_reg[0xF] = not_borrow as u8;
+            },
+
+            // 0x8XY6: (SHR Vx {, Vy})
+            // SHR = Single right-shift
+            // Set Vx = Vx >> 1, VF = LSB before shift
+            (8,_,_,6) => {
+                let x = digit2 as usize;
+                let y = digit3 as usize;
+
+                if self.quirks.shift_uses_vy {
+                    self.v_reg[x] = self.v_reg[y];
+                }
+
+                let lsb = self.v_reg[x] & 0x01;
+
+                self.v_reg[x] >>= 1;
+                self.v_reg[0xF] = lsb;
+            },
+
+            // 0x8XY7: (SUBN Vx, Vy)
+            // Set Vx = Vy - Vx, Set VF = NOT borrow
+            (8,_,_,7) => {
+                let x = digit2 as usize;
+                let y = digit3 as usize;
+
+                let not_borrow: bool = self.v_reg[y] >= self.v_reg[x];
+
+                self.v_reg[x] = self.v_reg[y].wrapping_sub(self.v_reg[x]);
+                self.v_reg[0xF] = not_borrow as u8;
+            },
+
+            // 0x8XYE: (SHL Vx {, Vy})
+            // Set Vx = Vx << 1, VF = MSB before shift
+            (8,_,_,0xE) => {
+                let x = digit2 as usize;
+                let y = digit3 as usize;
+
+                if self.quirks.shift_uses_vy {
+                    self.v_reg[x] = self.v_reg[y];
+                }
+
+                let msb: u8 = (self.v_reg[x] >> 7) & 0x01;
+
+                self.v_reg[x] <<= 1;
+                self.v_reg[0xF] = msb;
+            },
+
+            // 0x9XY0: (SNE Vx, Vy)
+            // Skip next instruction if Vx != Vy
+            (9,_,_,0) => {
+                let x = digit2 as usize;
+                let y = digit3 as usize;
+
+                if self.v_reg[x] != self.v_reg[y] {
+                    self.skip_next_instruction();
+                }
+            },
+
+            // 0xANNN: (LD I, addr)
+            // Set I = NNN
+            (0xA,_,_,_) => {
+                // NNN is only 12 bits, so this can never set I past 0xFFF in any
+                // variant. Addressing more memory needs F000 NNNN
+                self.i_reg = op & 0x0FFF;
+            },
+
+            // 0xBNNN: (JP V0, addr)
+            // Jump to location NNN + V0
+            (0xB,_,_,_) => {
+                if self.quirks.jump_uses_vx {
+                    self.pc = self.v_reg[digit2 as usize] as u16 + (op & 0x0FFF);
+                } else {
+                    self.pc = self.v_reg[0] as u16 + (op & 0x0FFF);
+                }
+            },
+
+            // 0xCXNN: RND Vx, byte
+            // Set Vx = random byte AND NN
+            (0xC,_,_,_) => {
+                let x = digit2 as usize;
+                
+                let rand: u8 = self.rng.gen();
+
+                self.v_reg[x] = rand & (op & 0x00FF) as u8;
+            },
+
+            // 0xDXYN: DRW Vx, Vy, nibble
+            // Display n-byte sprite starting at memory location I at (Vx, Vy), set VF = collision
+            // In SUPER-CHIP high resolution, DXY0 draws a 16x16 sprite instead
+            (0xD,_,_,_) => {
+                let (width, height) = self.display_dimensions();
+
+                // The starting position always wraps onto the screen
+                let x_coord = self.v_reg[digit2 as usize] as usize % width;
+                let y_coord = self.v_reg[digit3 as usize] as usize % height;
+
+                // The last digit determines how many rows high our sprite is.
+                // In high resolution DXY0 draws a 16x16 sprite instead, stored
+                // as two bytes per row. In low resolution it draws nothing
+                let (num_rows, row_bytes) = if self.hires && digit4 == 0 {
+                    (16, 2)
+                } else {
+                    (digit4, 1)
+                };
+                let sprite_width = row_bytes * 8;
+                
+                // Keep track if any pixels were flipped
+                let mut flipped = false;
+
+                // Each selected plane gets its own copy of the sprite, one
+                // after the other in memory starting at I
+                let sprite_len = (num_rows * row_bytes) as usize;
+                self.check_mem_range(self.i_reg as usize, sprite_len * self.selected_plane_indices().count())?;
+
+                for (nth_plane, plane) in self.selected_plane_indices().enumerate() {
+                    let sprite_addr = self.i_reg as usize + nth_plane * sprite_len;
+
+                    // Iterate over each row of our sprite
+                    for y_line in 0..num_rows {
+                        // Determine which memory address our row's data is stored
+                        let addr = sprite_addr + (y_line * row_bytes) as usize;
+
+                        // Join the row's bytes together, with the first byte as the leftmost pixels
+                        let mut pixels: u16 = 0;
+                        for byte in 0..row_bytes {
+                            pixels = (pixels << 8) | self.mem_read(addr + byte as usize) as u16;
+                        }
+
+                        // Iterate over each column in our row
+                        for x_line in 0..sprite_width {
+                            // Use a mask to fetch current pixel's bit. Only flip if a 1
+                            if (pixels & (1 << (sprite_width - 1 - x_line))) != 0 {
+                                let x = x_coord + x_line as usize;
+                                let y = y_coord + y_line as usize;
+
+                                // Parts of the sprite past the edge are either cut
+                                // off or wrapped around to the other side
+                                if self.quirks.clip_sprites && (x >= width || y >= height) {
+                                    continue;
+                                }
+                                let x = x % width;
+                                let y = y % height;
+
+                                // Get our pixel's index for our 1D screen array
+                                let idx = x + width * y;
+
+                                // Check if we're about to flip the pixel and set
+                                if self.screen[plane][idx] {
+                                    flipped = true;
+                                    if self.collision_pixels.len() < MAX_COLLISION_PIXELS {
+                                        self.collision_pixels.push((x as u8, y as u8));
+                                    }
+                                }
+                                self.screen[plane][idx] ^= true;
+                                self.display_dirty = true;
+                            }
+                        }
+                    }
+                }
+                // Populate VF register. A draw that turned no pixels off,
+                // including an empty sprite or one with no rows, clears it
+                if flipped {
+                    self.v_reg[0xF] = 1;
+                    self.collided = true;
+                } else {
+                    self.v_reg[0xF] = 0;
+                }
+
+                // On the VIP the draw holds the program until the next frame
+                if self.quirks.display_wait {
+                    self.vblank_wait = true;
+                }
+            },
+
+            // 0xEX9E: SKP Vx
+            // Skip next instruction if key with the value of Vx is pressed
+            (0xE,_,0x9,0xE) => {
+                let x = digit2 as usize;
+
+                // Like the VIP, only look at the low digit, so every value names a key
+                let key_no = (self.v_reg[x] & 0xF) as usize;
+
+                if self.keys[key_no] {
+                    self.skip_next_instruction();
+                }
+            },
+
+            // 0xEXA1: SKNP Vx
+            // Skip next instruction if key with the value of Vx is not pressed.
+            (0xE,_,0xA,0x1) => {
+                let x = digit2 as usize;
+
+                // As with SKP, only the low digit counts
+                let key_no = (self.v_reg[x] & 0xF) as usize;
+
+                if !self.keys[key_no] {
+                    self.skip_next_instruction();
+                }
+            },
+
+            // 0xF000 NNNN: (LD I, long addr) XO-CHIP
+            // Set I = NNNN, the 16-bit address in the word after the opcode
+            (0xF,0x0,0x0,0x0) => {
+                self.i_reg = self.fetch()?;
+            },
+
+            // 0xFN01: (PLANE n) XO-CHIP
+            // Select the bitplanes that drawing, scrolling, and CLS act on
+            (0xF,_,0x0,0x1) => {
+                self.planes = digit2 as u8 & 0x3;
+            },
+
+            // 0xF002: (AUDIO) XO-CHIP
+            // Load the 16-byte sample loop played while the sound timer runs from I
+            (0xF,0x0,0x0,0x2) => {
+                self.check_mem_range(self.i_reg as usize, AUDIO_PATTERN_SIZE)?;
+
+                let mut pattern = [0; AUDIO_PATTERN_SIZE];
+                for (offset, byte) in pattern.iter_mut().enumerate() {
+                    *byte = self.mem_read(self.i_reg as usize + offset);
+                }
+
+                self.audio_pattern = Some(pattern);
+            },
+
+            // 0xFX07: LD Vx, DT
+            // Set Vx = delay timer value.
+            (0xF,_,0x0,0x7) => {
+                let x = digit2 as usize;
+
+                self.v_reg[x] = self.dt;
+            },
+
+            // 0xFX0A: LD Vx, K
+            // Wait for a key press, store the value of the key in Vx.
+            // With the wait_for_key_release quirk, wait for it to be let go
+            (0xF,_,0x0,0xA) if self.quirks.wait_for_key_release => {
+                let x = digit2 as usize;
+
+                // Note each key that has been let go since we last looked
+                for i in 0..NUM_KEYS {
+                    if self.key_wait_held[i] && !self.keys[i] {
+                        self.key_wait_released = Some(i as u8);
+                    }
+                }
+                self.key_wait_held = self.keys;
+
+                // Finish once a key has been released and none are left held,
+                // with the last one let go. Otherwise repeat the instruction
+                match self.key_wait_released {
+                    Some(key_no) if !self.keys.contains(&true) => {
+                        self.v_reg[x] = key_no;
+                        self.key_wait_held = [false; NUM_KEYS];
+                        self.key_wait_released = None;
+                    },
+                    _ => {
+                        self.pc = self.pc.wrapping_sub(2);
+                        self.waiting_for_key = true;
+                    }
+                }
+            },
+
+            // Without the quirk, finish as soon as any key is down
+            (0xF,_,0x0,0xA) => {
+                let x = digit2 as usize;
+
+                // Initialize this to an invalid number initially
+                let mut key_no = NUM_KEYS as u8;
+
+                // Check if any of the keys have been pressed
+                for i in 0..NUM_KEYS {
+                    if self.keys[i] {
+                        key_no = i as u8;
+                    }
+                }
+
+                // If none have been pressed, repeat the same instruction
+                if key_no == NUM_KEYS as u8 {
+                    self.pc = self.pc.wrapping_sub(2);
+                    self.waiting_for_key = true;
+                } else {
+                    self.v_reg[x] = key_no;
+                }
+            },
+
+            // 0xFX15: LD DT, Vx
+            // Set delay timer = Vx
+            (0xF,_,0x1,0x5) => {
+                let x = digit2 as usize;
+
+                self.dt = self.v_reg[x];
+            },
+
+            // 0xFX18: LD ST, Vx
+            // Set sound timer = Vx
+            (0xF,_,0x1,0x8) => {
+                let x = digit2 as usize;
+
+                self.st = self.v_reg[x];
+            },
+
+            // 0xFX1E: ADD I, Vx
+            // Set I = I + Vx
+            (0xF,_,0x1,0xE) => {
+                let x = digit2 as usize;
+
+                let sum = self.i_reg as u32 + self.v_reg[x] as u32;
+                self.i_reg = sum as u16;
+
+                // I only addresses 12 bits, so some interpreters flag going past them
+                if self.quirks.add_i_overflow_sets_vf {
+                    self.v_reg[0xF] = (sum > 0x0FFF) as u8;
+                }
+            },
+
+            // 0xFX29: LD F, Vx
+            // Set I = location of sprite for digit Vx
+            (0xF,_,0x2,0x9) => {
+                let x = digit2 as usize;
+                let c = self.v_reg[x] as u16;
+
+                self.i_reg = self.font_addr + (FONT_SIZE as u16 * c);
+            },
+
+            // 0xFX30: LD HF, Vx SUPER-CHIP
+            // Set I = location of the big sprite for digit Vx
+            (0xF,_,0x3,0x0) => {
+                let x = digit2 as usize;
+                let c = self.v_reg[x] as u16;
+
+                self.i_reg = self.font_addr + FONTSET_SIZE as u16 + (BIG_FONT_SIZE as u16 * c);
+            },
+
+            // 0xFX33: LD B, Vx
+            // Store BCD representation of Vx in memory locations I, I+1, and I+2.
+            (0xF,_,0x3,0x3) => {
+                let x = digit2 as usize;
+                let vx = self.v_reg[x];
+
+                let hundreds = vx / 100;
+                let tens = (vx % 100) / 10;
+                let ones = vx % 10;
+
+                let start_addr = self.i_reg as usize;
+                self.check_mem_range(start_addr, 3)?;
+
+                self.mem_write(start_addr, hundreds);
+                self.mem_write(start_addr + 1, tens);
+                self.mem_write(start_addr + 2, ones);
+            },
+
+            // 0xFX3A: (PITCH Vx) XO-CHIP
+            // Set the playback rate of the sample loop
+            (0xF,_,0x3,0xA) => {
+                self.pitch = self.v_reg[digit2 as usize];
+            },
+
+            // 0xFX55: LD [I], Vx
+            // Store registers V0 through Vx in memory starting at location I.
+            (0xF,_,0x5,0x5) => {
+                let x = digit2 as usize;
+                let start_addr = self.i_reg as usize;
+                self.check_mem_range(start_addr, x + 1)?;
+
+                for i in 0..=x {
+                    self.mem_write(start_addr + i, self.v_reg[i]);
+                }
+
+                if self.quirks.load_store_increments_i {
+                    self.i_reg = self.i_reg.wrapping_add(x as u16 + 1);
+                }
+            },
+
+            // 0xFX65: LD Vx, [I]
+            // Read registers V0 through Vx from memory starting at location I.
+            (0xF,_,0x6,0x5) => {
+                let x = digit2 as usize;
+                let start_addr = self.i_reg as usize;
+                self.check_mem_range(start_addr, x + 1)?;
+
+                for i in 0..=x {
+                    self.v_reg[i] = self.mem_read(start_addr + i);
+                }
+
+                if self.quirks.load_store_increments_i {
+                    self.i_reg = self.i_reg.wrapping_add(x as u16 + 1);
+                }
+            }
+
+
+            (_,_,_,_) => self.unknown_opcode(op)?
+        }
+
+        Ok(())
+    }
+
+    // The instructions starting with 0. Those with a second digit of 0 each
+    // do their own thing; the rest are 0NNN, which on the original machine
+    // ran the processor's own machine code at NNN
+    fn execute_system(&mut self, op: u16) -> Result<(), Chip8Error> {
+        match op {
+            // 0x0000: No operation (NOP)
+            0x0000 => (),
+
+            // 0x00CN: (SCD nibble) SUPER-CHIP
+            // Scroll the display down N pixels
+            0x00C0..=0x00CF => {
+                self.scroll_display(0, (op & 0x000F) as isize);
+            },
+
+            // 0x00DN: (SCU nibble) XO-CHIP
+            // Scroll the display up N pixels
+            0x00D0..=0x00DF => {
+                self.scroll_display(0, -((op & 0x000F) as isize));
+            },
+
+            // 0x00E0: (CLS)
+            // Clear the selected planes of the screen
+            0x00E0 => {
+                for plane in self.selected_plane_indices() {
+                    self.screen[plane] = [false; SCREEN_BUF_SIZE];
+                }
+                self.display_dirty = true;
+            },
+
+            // 0x00EE: (RET)
+            // Return from subroutine
+            0x00EE => {
+                // Pop the return address from the stack change the program counter value
+                let ret_addr = self.pop()?;
+                self.pc = ret_addr;
+            },
+
+            // 0x00FB: (SCR) SUPER-CHIP
+            // Scroll the display right 4 pixels
+            0x00FB => {
+                self.scroll_display(4, 0);
+            },
+
+            // 0x00FC: (SCL) SUPER-CHIP
+            // Scroll the display left 4 pixels
+            0x00FC => {
+                self.scroll_display(-4, 0);
+            },
+
+            // 0x00FE: (LOW) SUPER-CHIP
+            // Switch to the 64x32 low resolution display, clearing the screen
+            0x00FE => {
+                self.hires = false;
+                self.screen = [[false; SCREEN_BUF_SIZE]; NUM_PLANES];
+                self.display_dirty = true;
+            },
+
+            // 0x00FF: (HIGH) SUPER-CHIP
+            // Switch to the 128x64 high resolution display, clearing the screen
+            0x00FF => {
+                self.hires = true;
+                self.screen = [[false; SCREEN_BUF_SIZE]; NUM_PLANES];
+                self.display_dirty = true;
+            },
+
+            // Anything else in 0x00__ isn't an instruction we know
+            0x0001..=0x00FF => self.unknown_opcode(op)?,
+
+            // 0x0NNN: (SYS addr)
+            // There's no COSMAC processor here to run machine code on, so
+            // rather than skip the call and carry on wrongly, stop on it
+            _ => {
+                self.pc = self.pc.wrapping_sub(2);
+                return Err(Chip8Error::MachineCodeCall(op & 0x0FFF));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Stop on an opcode no handler recognized, or pause on it if asked to
+    fn unknown_opcode(&mut self, op: u16) -> Result<(), Chip8Error> {
+        // Step back so PC points at the bad opcode for whoever inspects it
+        self.pc = self.pc.wrapping_sub(2);
+
+        if self.pause_on_unknown_op {
+            self.paused = true;
+        } else {
+            return Err(Chip8Error::UnknownOpcode(op));
+        }
+
+        Ok(())
+    }
+
+    fn fetch(&mut self) -> Result<u16, Chip8Error> {
+        // Both bytes of the instruction have to be in RAM
+        if self.pc as usize + 1 >= self.ram.len() {
+            return Err(Chip8Error::PcOutOfBounds(self.pc));
+        }
+
+        // Real hardware reads the two bytes wherever PC is, and a few ROMs
+        // rely on that, so odd addresses are only refused when asked to
+        if self.quirks.strict_alignment && !self.pc.is_multiple_of(2) {
+            return Err(Chip8Error::MisalignedPc(self.pc));
+        }
+
+        let higher_byte = self.mem_read(self.pc as usize) as u16;
+        let lower_byte = self.mem_read((self.pc + 1) as usize) as u16;
+
+        let op = (higher_byte << 8) | lower_byte;
+
+        // With 64KB of RAM the last instruction leaves PC wrapping round to 0
+        self.pc = self.pc.wrapping_add(2);
+
+        Ok(op)
+    }
+
+    // Move PC past the instruction it points at, for the skip instructions.
+    // XO-CHIP's F000 NNNN is twice as long as the rest, so it's skipped whole
+    fn skip_next_instruction(&mut self) {
+        let len = if self.peek_opcode() == Some(0xF000) { 4 } else { 2 };
+        self.pc = self.pc.wrapping_add(len);
+    }
+
+    // Indices into screen of the planes selected with FN01, first plane first
+    fn selected_plane_indices(&self) -> impl Iterator<Item = usize> {
+        let planes = self.planes;
+        (0..NUM_PLANES).filter(move |plane| planes & (1 << plane) != 0)
+    }
+
+    // Shift the selected planes of the active display by (dx, dy) pixels.
+    // Pixels pushed off the edge are lost, and the rows/columns left behind
+    // are unlit
+    fn scroll_display(&mut self, dx: isize, dy: isize) {
+        let (width, height) = self.display_dimensions();
+
+        for plane in self.selected_plane_indices() {
+            let mut scrolled = [false; SCREEN_BUF_SIZE];
+
+            for y in 0..height {
+                for x in 0..width {
+                    let src_x = x as isize - dx;
+                    let src_y = y as isize - dy;
+
+                    if src_x >= 0 && src_y >= 0 && (src_x as usize) < width && (src_y as usize) < height {
+                        scrolled[x + width * y] = self.screen[plane][src_x as usize + width * src_y as usize];
+                    }
+                }
+            }
+
+            self.screen[plane] = scrolled;
+        }
+
+        self.display_dirty = true;
+    }
+
+    // Fail with MemoryOutOfBounds unless the len bytes from start are all in
+    // RAM, so an instruction can check everything it touches before it
+    // changes anything. Called while executing, after PC has moved past the
+    // instruction
+    fn check_mem_range(&self, start: usize, len: usize) -> Result<(), Chip8Error> {
+        if start + len > self.ram.len() {
+            return Err(Chip8Error::MemoryOutOfBounds {
+                pc: self.pc.wrapping_sub(2),
+                addr: start.max(self.ram.len())
+            });
+        }
+
+        Ok(())
+    }
+
+    // Read a byte of RAM on behalf of the running program
+    fn mem_read(&mut self, addr: usize) -> u8 {
+        self.heat[addr] = self.heat[addr].saturating_add(1);
+        self.ram[addr]
+    }
+
+    // Write a byte of RAM on behalf of the running program
+    fn mem_write(&mut self, addr: usize, val: u8) {
+        self.heat[addr] = self.heat[addr].saturating_add(1);
+        self.ram[addr] = val;
+    }
+
+    pub fn tick_timers(&mut self){
+        // tick_timers runs once per frame, so this starts a new frame
+        self.collided = false;
+        self.collision_pixels.clear();
+        self.vblank_wait = false;
+
+        // In PerTick mode the delay timer is counted down by tick instead
+        if self.timer_mode == TimerMode::PerFrame && self.dt > 0 {
+            self.dt -= 1;
+        }
+
+        // The frontend beeps for as long as the sound timer is running (see is_beeping)
+        if self.st > 0 {
+            self.st -= 1;
+        }
+    }
+
+    // Whether any sprite draw has collided (set VF to 1) since the last tick_timers
+    pub fn collision_this_frame(&self) -> bool {
+        self.collided
+    }
+
+    // The (x, y) position of every pixel a sprite draw has erased since the
+    // last tick_timers, in the order they were erased. A pixel erased by more
+    // than one draw is listed each time. Only the first MAX_COLLISION_PIXELS
+    // are kept
+    pub fn collision_pixels(&self) -> &[(u8, u8)] {
+        &self.collision_pixels
+    }
+
+    // Whether a draw under the display_wait quirk is holding the program until
+    // the next tick_timers. Ticks do nothing until then, so however many
+    // instructions a frontend runs per frame, at most one sprite is drawn per
+    // frame and the rest of that frame's ticks are skipped. Frontends can
+    // check this to stop their tick loop early
+    pub fn is_waiting_for_vblank(&self) -> bool {
+        self.vblank_wait
+    }
+
+    // Whether the sound timer is running, meaning the frontend should play a tone
+    pub fn is_beeping(&self) -> bool {
+        self.st > 0
+    }
+
+    // The 128 one-bit samples loaded by XO-CHIP's F002, most significant bit
+    // of the first byte first. All zero until a program loads some; see
+    // has_audio_pattern
+    pub fn audio_pattern(&self) -> &[u8; AUDIO_PATTERN_SIZE] {
+        const SILENT: [u8; AUDIO_PATTERN_SIZE] = [0; AUDIO_PATTERN_SIZE];
+        self.audio_pattern.as_ref().unwrap_or(&SILENT)
+    }
+
+    // Whether the program has loaded a sample loop. Until it does, frontends
+    // should play their usual beep while is_beeping
+    pub fn has_audio_pattern(&self) -> bool {
+        self.audio_pattern.is_some()
+    }
+
+    // The pitch set by FX3A, DEFAULT_PITCH until a program changes it. See
+    // pattern_playback_rate for the rate this plays audio_pattern at
+    pub fn audio_pitch(&self) -> u8 {
+        self.pitch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    // A fresh emulator with the program loaded at START_ADDR
+    fn emu_with(program: &[u16]) -> Emu {
+        let mut emu = Emu::with_seed(0);
+        emu.load(&asm::to_bytes(program)).unwrap();
+        emu
+    }
+
+    #[test]
+    fn flicker_metric_is_high_for_flickering_frames() {
+        let emu = Emu::new();
+        let lit = vec![true; SCREEN_WIDTH * SCREEN_HEIGHT];
+        let dark = vec![false; SCREEN_WIDTH * SCREEN_HEIGHT];
+
+        let frames = [lit.clone(), dark.clone(), lit, dark];
+
+        assert_eq!(emu.flicker_metric(&frames), 1.0);
+    }
+
+    #[test]
+    fn flicker_metric_is_low_for_stable_frames() {
+        let emu = Emu::new();
+        let mut frame = vec![false; SCREEN_WIDTH * SCREEN_HEIGHT];
+        frame[0] = true;
+        frame[100] = true;
+
+        let frames = vec![frame; 4];
+
+        assert_eq!(emu.flicker_metric(&frames), 0.0);
+    }
+
+    #[test]
+    fn test_pattern_draws_border_and_crosshair() {
+        let mut emu = Emu::new();
+        emu.load_test_pattern();
+
+        // Corners and edges
+        for (x, y) in [(0, 0), (63, 0), (0, 31), (63, 31), (10, 0), (0, 10), (63, 20), (40, 31)] {
+            assert!(emu.pixel(x, y), "({}, {}) should be lit", x, y);
+        }
+
+        // Crosshair through the center
+        for (x, y) in [(32, 5), (32, 16), (32, 25), (5, 16), (50, 16)] {
+            assert!(emu.pixel(x, y), "({}, {}) should be lit", x, y);
+        }
+
+        // Everywhere in between is dark
+        for (x, y) in [(1, 1), (31, 15), (33, 17), (62, 30), (10, 10)] {
+            assert!(!emu.pixel(x, y), "({}, {}) should be dark", x, y);
+        }
+
+        // The top, bottom, and middle rows are all lit, and the other rows have
+        // just the two edges and the vertical line
+        let lit = emu.get_display().iter().filter(|p| **p).count();
+        assert_eq!(lit, 64 * 3 + 29 * 3);
+    }
+
+    #[test]
+    fn add_imm_leaves_vf_alone_by_default() {
+        let mut emu = emu_with(&[asm::ld_vx_byte(0xF, 0xAA), asm::ld_vx_byte(0, 0xF0), asm::add_vx_byte(0, 0x20)]);
+        emu.run(3).unwrap();
+
+        assert_eq!(emu.registers()[0], 0x10);
+        assert_eq!(emu.registers()[0xF], 0xAA);
+    }
+
+    #[test]
+    fn add_imm_sets_vf_on_overflow_under_quirk() {
+        let program = [asm::ld_vx_byte(0xF, 0xAA), asm::ld_vx_byte(0, 0xF0), asm::add_vx_byte(0, 0x20), asm::add_vx_byte(0, 0x01)];
+        let mut emu = emu_with(&program);
+        emu.set_quirks(Quirks { add_imm_sets_vf: true, ..Quirks::default() });
+
+        emu.run(3).unwrap();
+        assert_eq!(emu.registers()[0], 0x10);
+        assert_eq!(emu.registers()[0xF], 1);
+
+        // An add that doesn't overflow clears it
+        emu.run(1).unwrap();
+        assert_eq!(emu.registers()[0], 0x11);
+        assert_eq!(emu.registers()[0xF], 0);
+    }
+
+    #[test]
+    fn se_vx_vy_does_not_skip_unequal_registers() {
+        let mut emu = emu_with(&[asm::ld_vx_byte(1, 3), asm::ld_vx_byte(2, 4), asm::se_vx_vy(1, 2)]);
+        emu.run(3).unwrap();
+
+        assert_eq!(emu.program_counter(), 0x206);
+    }
+
+    #[test]
+    fn se_vx_vy_skips_equal_registers() {
+        let mut emu = emu_with(&[asm::ld_vx_byte(1, 3), asm::ld_vx_byte(2, 3), asm::se_vx_vy(1, 2)]);
+        emu.run(3).unwrap();
+
+        assert_eq!(emu.program_counter(), 0x208);
+    }
+
+    #[test]
+    fn keypad_shows_pressed_keys() {
+        let mut emu = Emu::new();
+        assert_eq!(emu.keypad(), &[false; NUM_KEYS]);
+
+        emu.keypress(0xA, true);
+
+        let mut expected = [false; NUM_KEYS];
+        expected[0xA] = true;
+        assert_eq!(emu.keypad(), &expected);
+
+        emu.set_key_state(&[true; NUM_KEYS]);
+        assert_eq!(emu.keypad(), &[true; NUM_KEYS]);
+    }
+
+    #[test]
+    fn store_and_load_registers_round_trip() {
+        let values = [0x11, 0x22, 0x33, 0x44, 0x55, 0x66];
+
+        let mut program: Vec<u16> = (0..6).map(|x| asm::ld_vx_byte(x, values[x as usize])).collect();
+        program.extend([asm::ld_i(0x300), asm::ld_i_vx(5)]);
+        program.extend((0..6).map(|x| asm::ld_vx_byte(x, 0)));
+        program.extend([asm::ld_i(0x300), asm::ld_vx_i(5)]);
+
+        let mut emu = emu_with(&program);
+
+        // Store V0 through V5, including V5 itself, and nothing after it
+        emu.run(8).unwrap();
+        for (offset, value) in values.iter().enumerate() {
+            assert_eq!(emu.read_ram(0x300 + offset as u16), Some(*value));
+        }
+        assert_eq!(emu.read_ram(0x306), Some(0));
+
+        // Zero them, then load them back
+        emu.run(6).unwrap();
+        assert_eq!(&emu.registers()[..6], &[0; 6]);
+
+        emu.run(2).unwrap();
+        assert_eq!(&emu.registers()[..6], &values);
+    }
+
+    #[test]
+    fn braille_packs_2x4_blocks_by_dot_number() {
+        let mut emu = Emu::new();
+        emu.screen[0][0] = true;                        // (0, 0): dot 1 of the first cell
+        emu.screen[0][3 + SCREEN_WIDTH * 3] = true;     // (3, 3): dot 8 of the second
+
+        // Every dot of the third cell on the second row
+        for (x, y) in [(4, 4), (5, 4), (4, 5), (5, 5), (4, 6), (5, 6), (4, 7), (5, 7)] {
+            emu.screen[0][x + SCREEN_WIDTH * y] = true;
+        }
+
+        let braille = emu.display_braille();
+        let lines: Vec<Vec<char>> = braille.lines().map(|line| line.chars().collect()).collect();
+
+        assert_eq!(lines.len(), 8);
+        assert!(lines.iter().all(|line| line.len() == 32));
+        assert_eq!(lines[0][0], '\u{2801}');
+        assert_eq!(lines[0][1], '\u{2880}');
+        assert_eq!(lines[0][2], '\u{2800}');
+        assert_eq!(lines[1][2], '\u{28FF}');
+    }
+
+    #[test]
+    fn bcd_stores_digits_of_register_value() {
+        let mut emu = emu_with(&[asm::ld_vx_byte(4, 234), asm::ld_i(0x300), asm::ld_b_vx(4)]);
+        emu.run(3).unwrap();
+
+        assert_eq!(emu.read_ram(0x300), Some(2));
+        assert_eq!(emu.read_ram(0x301), Some(3));
+        assert_eq!(emu.read_ram(0x302), Some(4));
+    }
+
+    #[test]
+    fn step_skip_nops_runs_past_leading_nops() {
+        let mut emu = emu_with(&[0x0000, 0x0000, 0x0000, asm::ld_vx_byte(3, 0x2A), asm::ld_vx_byte(4, 1)]);
+
+        let traced = Arc::new(Mutex::new(Vec::new()));
+        let log = traced.clone();
+        emu.set_trace_callback(Some(Box::new(move |pc, _| log.lock().unwrap().push(pc))));
+
+        assert_eq!(emu.step_skip_nops(), Ok(Some(0x632A)));
+        assert_eq!(emu.registers()[3], 0x2A);
+        assert_eq!(emu.registers()[4], 0);
+        assert_eq!(emu.program_counter(), 0x208);
+
+        // The NOPs were run like any other instruction
+        assert_eq!(emu.instruction_stats().count(0x0), 3);
+        assert_eq!(*traced.lock().unwrap(), vec![0x200, 0x202, 0x204, 0x206]);
+    }
+
+    #[test]
+    fn step_skip_nops_does_nothing_while_held() {
+        let mut emu = emu_with(&[asm::drw(0, 0, 1), 0x0000, asm::drw(0, 0, 1)]);
+        emu.set_quirks(Quirks { display_wait: true, ..Quirks::default() });
+
+        // The first draw waits for the next frame, so the second can't happen yet
+        assert_eq!(emu.step_skip_nops(), Ok(Some(0xD001)));
+        assert_eq!(emu.step_skip_nops(), Ok(None));
+        assert_eq!(emu.program_counter(), 0x202);
+
+        emu.tick_timers();
+        emu.set_paused(true);
+        assert_eq!(emu.step_skip_nops(), Ok(None));
+        assert_eq!(emu.program_counter(), 0x202);
+
+        emu.set_paused(false);
+        assert_eq!(emu.step_skip_nops(), Ok(Some(0xD001)));
+        assert_eq!(emu.program_counter(), 0x206);
+    }
+
+    #[test]
+    fn step_skip_nops_gives_up_on_endless_nops() {
+        // With 64KB, PC wraps from the top of RAM to the bottom, so RAM that's
+        // nothing but NOPs is an endless loop
+        let mut emu = Emu::with_memory_size(MAX_RAM_SIZE);
+        for addr in 0..(FONTSET_SIZE + BIG_FONTSET_SIZE) as u16 {
+            emu.write_ram(addr, 0).unwrap();
+        }
+
+        assert_eq!(emu.step_skip_nops(), Err(Chip8Error::NopLimitExceeded(START_ADDR)));
+    }
+
+    #[test]
+    fn ld_i_takes_twelve_bits_in_both_memory_sizes() {
+        for memory_size in [RAM_SIZE, MAX_RAM_SIZE] {
+            let mut emu = EmuBuilder::new().memory_size(memory_size).build();
+            emu.load(&asm::to_bytes(&[0xAFFF, 0xA123])).unwrap();
+
+            emu.run(1).unwrap();
+            assert_eq!(emu.i_register(), 0x0FFF);
+            emu.run(1).unwrap();
+            assert_eq!(emu.i_register(), 0x0123);
+        }
+    }
+
+    #[test]
+    fn long_ld_i_sets_all_sixteen_bits() {
+        let mut emu = EmuBuilder::new().memory_size(MAX_RAM_SIZE).build();
+        emu.load(&asm::to_bytes(&[asm::ld_i(0x123), asm::ld_i_long(), 0xBEEF, asm::ld_vx_byte(0, 1)])).unwrap();
+
+        emu.run(2).unwrap();
+        assert_eq!(emu.i_register(), 0xBEEF);
+        assert_eq!(emu.program_counter(), 0x206);
+
+        // The address word was skipped over rather than run
+        emu.run(1).unwrap();
+        assert_eq!(emu.registers()[0], 1);
+    }
+
+    #[test]
+    fn load_rejects_rom_too_large_for_ram() {
+        let mut emu = Emu::new();
+
+        assert_eq!(emu.load(&[0xAB; 4000]), Err(LoadError::TooLarge { len: 4000, max: 3584 }));
+        assert_eq!(emu.read_ram(START_ADDR), Some(0));
+
+        // The largest ROM that fits still loads
+        assert_eq!(emu.load(&[0xAB; 3584]), Ok(None));
+    }
+
+    #[test]
+    fn access_heat_counts_repeated_accesses() {
+        let mut emu = emu_with(&[asm::ld_i(0x300), asm::ld_vx_i(0), asm::ld_vx_i(0), asm::ld_vx_i(0)]);
+        emu.set_quirks(Quirks { load_store_increments_i: false, ..Quirks::default() });
+        emu.run(4).unwrap();
+
+        let heat = emu.access_heat();
+        assert_eq!(heat[0x300], 3);
+        assert_eq!(heat[0x301], 0);
+
+        // Fetching an instruction reads both of its bytes
+        assert_eq!(heat[0x200], 1);
+        assert_eq!(heat[0x201], 1);
+        assert!(heat[0x300] > heat[0x200]);
+
+        emu.reset();
+        assert!(emu.access_heat().iter().all(|count| *count == 0));
+    }
+
+    #[test]
+    fn seventeen_nested_calls_overflow_the_stack() {
+        // Each instruction calls the one after it
+        let program: Vec<u16> = (1..=17).map(|i| asm::call(START_ADDR + 2 * i)).collect();
+        let mut emu = emu_with(&program);
+
+        emu.run(16).unwrap();
+        assert_eq!(emu.stack_pointer(), 16);
+
+        assert_eq!(emu.tick(), Err(Chip8Error::StackOverflow));
+    }
+
+    #[test]
+    fn return_with_empty_stack_underflows() {
+        let mut emu = emu_with(&[asm::ret()]);
+
+        assert_eq!(emu.tick(), Err(Chip8Error::StackUnderflow));
+        assert_eq!(emu.stack_pointer(), 0);
+    }
+
+    #[test]
+    fn unknown_opcode_is_an_error_by_default() {
+        let mut emu = emu_with(&[asm::ld_vx_byte(0, 1), 0xE0FF]);
+
+        assert_eq!(emu.run(2), Err(Chip8Error::UnknownOpcode(0xE0FF)));
+        assert!(!emu.is_paused());
+    }
+
+    #[test]
+    fn unknown_opcode_pauses_when_asked() {
+        let mut emu = emu_with(&[asm::ld_vx_byte(0, 1), 0xE0FF, asm::ld_vx_byte(0, 2)]);
+        emu.set_pause_on_unknown_opcode(true);
+
+        emu.run(2).unwrap();
+        assert!(emu.is_paused());
+        assert_eq!(emu.last_opcode(), 0xE0FF);
+        assert_eq!(emu.program_counter(), 0x202);
+
+        // Nothing more runs until unpaused
+        emu.run(5).unwrap();
+        assert_eq!(emu.program_counter(), 0x202);
+        assert_eq!(emu.registers()[0], 1);
+    }
+
+    #[test]
+    fn state_hash_changes_only_with_the_state() {
+        let program = [asm::ld_vx_byte(0, 5)];
+        let mut emu = emu_with(&program);
+        let before = emu.state_hash();
+
+        // The same machine, or an identical one, hashes the same every time
+        assert_eq!(emu.state_hash(), before);
+        assert_eq!(emu_with(&program).state_hash(), before);
+
+        emu.run(1).unwrap();
+        assert_ne!(emu.state_hash(), before);
+
+        // Bookkeeping outside the machine, like the instruction counts, isn't hashed
+        let after = emu.state_hash();
+        emu.reset_stats();
+        assert_eq!(emu.state_hash(), after);
+    }
+
+    #[test]
+    fn big_sprite_puts_first_byte_of_each_row_on_the_left() {
+        let mut sprite = [0u16; 16];
+        sprite[0] = 0xFF00;
+        sprite[1] = 0x00FF;
+        sprite[15] = 0x8001;
+
+        let mut program = vec![asm::high(), asm::ld_i(0x208), asm::drw(0, 0, 0), asm::jp(0x206)];
+        program.extend(sprite);
+        let mut emu = emu_with(&program);
+        emu.run(3).unwrap();
+
+        let mut expected: Vec<(usize, usize)> = (0..8).map(|x| (x, 0)).collect();
+        expected.extend((8..16).map(|x| (x, 1)));
+        expected.extend([(0, 15), (15, 15)]);
+
+        assert_eq!(emu.lit_pixels().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn load_store_moves_i_past_the_registers_by_default() {
+        let mut emu = emu_with(&[asm::ld_i(0x300), asm::ld_i_vx(3), asm::ld_vx_i(1)]);
+
+        emu.run(2).unwrap();
+        assert_eq!(emu.i_register(), 0x304);
+        emu.run(1).unwrap();
+        assert_eq!(emu.i_register(), 0x306);
+    }
+
+    #[test]
+    fn load_store_leaves_i_alone_without_quirk() {
+        let mut emu = emu_with(&[asm::ld_i(0x300), asm::ld_i_vx(3), asm::ld_vx_i(1)]);
+        emu.set_quirks(Quirks { load_store_increments_i: false, ..Quirks::default() });
+
+        emu.run(3).unwrap();
+        assert_eq!(emu.i_register(), 0x300);
+    }
+
+    // Run SHR or SHL V1, V2 with V1 = 0x03 and V2 = 0x80, returning V1 and VF
+    fn shift_result(shift: u16, shift_uses_vy: bool) -> (u8, u8) {
+        let mut emu = emu_with(&[asm::ld_vx_byte(1, 0x03), asm::ld_vx_byte(2, 0x80), shift]);
+        emu.set_quirks(Quirks { shift_uses_vy, ..Quirks::default() });
+        emu.run(3).unwrap();
+
+        (emu.registers()[1], emu.registers()[0xF])
+    }
+
+    #[test]
+    fn shifts_work_on_vx_in_place_by_default() {
+        assert!(!Quirks::default().shift_uses_vy);
+
+        assert_eq!(shift_result(asm::shr(1, 2), false), (0x01, 1));
+        assert_eq!(shift_result(asm::shl(1, 2), false), (0x06, 0));
+    }
+
+    #[test]
+    fn shifts_copy_vy_first_under_quirk() {
+        assert_eq!(shift_result(asm::shr(1, 2), true), (0x40, 0));
+        assert_eq!(shift_result(asm::shl(1, 2), true), (0x00, 1));
+    }
+
+    #[test]
+    fn jump_with_offset_adds_v0_by_default() {
+        let mut emu = emu_with(&[asm::ld_vx_byte(0, 0x10), asm::ld_vx_byte(2, 0x20), 0xB230]);
+        emu.run(3).unwrap();
+
+        assert_eq!(emu.program_counter(), 0x240);
+    }
+
+    #[test]
+    fn jump_with_offset_adds_vx_under_quirk() {
+        let mut emu = emu_with(&[asm::ld_vx_byte(0, 0x10), asm::ld_vx_byte(2, 0x20), 0xB230]);
+        emu.set_quirks(Quirks { jump_uses_vx: true, ..Quirks::default() });
+        emu.run(3).unwrap();
+
+        assert_eq!(emu.program_counter(), 0x250);
+    }
+
+    #[test]
+    fn stop_reason_is_none_until_something_runs() {
+        let mut emu = emu_with(&[asm::ld_vx_byte(0, 1)]);
+        assert_eq!(emu.last_stop_reason(), None);
+
+        emu.tick().unwrap();
+        assert_eq!(emu.last_stop_reason(), None);
+
+        emu.run(1).unwrap();
+        emu.reset();
+        assert_eq!(emu.last_stop_reason(), None);
+    }
+
+    #[test]
+    fn stop_reason_completed() {
+        let mut emu = emu_with(&[asm::ld_vx_byte(0, 1), asm::ld_vx_byte(1, 2), asm::jp(0x200)]);
+
+        emu.run(10).unwrap();
+        assert_eq!(emu.last_stop_reason(), Some(StopReason::Completed));
+    }
+
+    #[test]
+    fn stop_reason_budget_exceeded() {
+        let mut emu = emu_with(&[asm::add_vx_byte(0, 1), asm::jp(0x200)]);
+
+        assert_eq!(emu.run_until_halt(10), Ok(StopReason::BudgetExceeded));
+        assert_eq!(emu.last_stop_reason(), Some(StopReason::BudgetExceeded));
+        assert_eq!(emu.registers()[0], 5);
+    }
+
+    #[test]
+    fn stop_reason_halted() {
+        let mut emu = emu_with(&[asm::ld_vx_byte(0, 1), asm::jp(0x202)]);
+
+        assert_eq!(emu.run_until_halt(10), Ok(StopReason::Halted));
+        assert_eq!(emu.last_stop_reason(), Some(StopReason::Halted));
+        assert_eq!(emu.instruction_stats().total(), 2);
+    }
+
+    #[test]
+    fn stop_reason_waiting_for_key() {
+        let mut emu = emu_with(&[asm::ld_vx_k(0)]);
+
+        assert_eq!(emu.run_until_halt(10), Ok(StopReason::WaitingForKey));
+        assert!(!emu.is_halted());
+
+        // run carries on repeating the wait
+        emu.run(10).unwrap();
+        assert_eq!(emu.last_stop_reason(), Some(StopReason::Completed));
+    }
+
+    #[test]
+    fn stop_reason_waiting_for_vblank() {
+        let mut emu = emu_with(&[asm::drw(0, 0, 1), asm::drw(0, 0, 1)]);
+        emu.set_quirks(Quirks { display_wait: true, ..Quirks::default() });
+
+        assert_eq!(emu.run_until_halt(10), Ok(StopReason::WaitingForVblank));
+        emu.run(10).unwrap();
+        assert_eq!(emu.last_stop_reason(), Some(StopReason::WaitingForVblank));
+        assert_eq!(emu.instruction_stats().total(), 1);
+    }
+
+    #[test]
+    fn stop_reason_unknown_opcode() {
+        let mut emu = emu_with(&[asm::ld_vx_byte(0, 1), 0xE0FF]);
+        emu.set_pause_on_unknown_opcode(true);
+
+        emu.run(10).unwrap();
+        assert_eq!(emu.last_stop_reason(), Some(StopReason::UnknownOpcode(0xE0FF)));
+
+        // It's still paused, so the next run doesn't get anywhere
+        assert_eq!(emu.run_until_halt(10), Ok(StopReason::Paused));
+    }
+
+    #[test]
+    fn stop_reason_paused() {
+        let mut emu = emu_with(&[asm::ld_vx_byte(0, 1)]);
+        emu.set_paused(true);
+
+        emu.run(10).unwrap();
+        assert_eq!(emu.last_stop_reason(), Some(StopReason::Paused));
+        assert_eq!(emu.registers()[0], 0);
+    }
+
+    #[test]
+    fn stop_reason_error() {
+        let mut emu = emu_with(&[asm::ret()]);
+
+        assert_eq!(emu.run_until_halt(10), Err(Chip8Error::StackUnderflow));
+        assert_eq!(emu.last_stop_reason(), Some(StopReason::Error(Chip8Error::StackUnderflow)));
+    }
+
+    #[test]
+    fn debugger_accessors_show_the_machine() {
+        let mut emu = emu_with(&[asm::ld_vx_byte(3, 0x2A), asm::ld_i(0x22A), asm::jp(0x300)]);
+        assert_eq!(emu.program_counter(), START_ADDR);
+
+        emu.run(3).unwrap();
+
+        assert_eq!(emu.program_counter(), 0x300);
+        assert_eq!(emu.registers().len(), 16);
+        assert_eq!(emu.registers()[3], 0x2A);
+        assert_eq!(emu.i_register(), 0x22A);
+    }
+
+    // Set DT to 10, switch to the timer mode, then read DT into V1 to V4 on
+    // four ticks in a row and end the frame
+    fn dt_reads(mode: TimerMode) -> (Vec<u8>, u8) {
+        let mut program = vec![asm::ld_vx_byte(0, 10), asm::ld_dt_vx(0)];
+        program.extend((1..=4).map(asm::ld_vx_dt));
+        let mut emu = emu_with(&program);
+
+        emu.run(2).unwrap();
+        emu.set_timer_mode(mode);
+        emu.run(4).unwrap();
+        emu.tick_timers();
+
+        (emu.registers()[1..=4].to_vec(), emu.delay_timer())
+    }
+
+    #[test]
+    fn dt_holds_still_within_a_frame_by_default() {
+        assert_eq!(dt_reads(TimerMode::PerFrame), (vec![10, 10, 10, 10], 9));
+    }
+
+    #[test]
+    fn dt_counts_down_between_ticks_in_per_tick_mode() {
+        // tick_timers leaves DT to the instructions in this mode
+        assert_eq!(dt_reads(TimerMode::PerTick { ticks_per_frame: 2 }), (vec![10, 10, 9, 9], 8));
+    }
+
+    #[test]
+    fn fetch_from_the_last_byte_of_ram_is_out_of_bounds() {
+        let mut emu = emu_with(&[]);
+        emu.pc = (RAM_SIZE - 1) as u16;
+
+        assert_eq!(emu.tick(), Err(Chip8Error::PcOutOfBounds(0xFFF)));
+    }
+
+    #[test]
+    fn running_off_the_end_of_ram_is_out_of_bounds() {
+        // The last full instruction at 0xFFE is a NOP, after which PC lands
+        // on 0x1000
+        let mut emu = emu_with(&[asm::jp(0xFFE)]);
+
+        emu.run(2).unwrap();
+        assert_eq!(emu.tick(), Err(Chip8Error::PcOutOfBounds(0x1000)));
+    }
+
+    #[test]
+    fn stack_round_trips_through_export_and_import() {
+        let mut emu = emu_with(&[asm::call(0x204), asm::jp(0x200), asm::call(0x208), asm::jp(0x206), asm::jp(0x208)]);
+        emu.run(3).unwrap();
+
+        let (entries, sp) = emu.export_stack();
+        assert_eq!(sp, 2);
+        assert_eq!(&entries[..2], &[0x202, 0x206]);
+
+        let mut other = emu_with(&[]);
+        other.import_stack(&entries, sp).unwrap();
+        assert_eq!(other.export_stack(), (entries, sp));
+    }
+
+    #[test]
+    fn import_stack_rejects_what_doesnt_fit() {
+        let mut emu = emu_with(&[]);
+
+        assert_eq!(emu.import_stack(&[0; STACK_SIZE + 1], 0), Err(Chip8Error::InvalidStack));
+        assert_eq!(emu.import_stack(&[0x202], STACK_SIZE as u16 + 1), Err(Chip8Error::InvalidStack));
+        assert_eq!(emu.export_stack(), (vec![0; STACK_SIZE], 0));
+    }
+
+    #[test]
+    fn pixel_reads_back_a_drawn_dot() {
+        // Draw the single lit pixel of the sprite byte at 0x20A at (10, 5)
+        let mut emu = emu_with(&[
+            asm::ld_vx_byte(0, 10),
+            asm::ld_vx_byte(1, 5),
+            asm::ld_i(0x20A),
+            asm::drw(0, 1, 1),
+            asm::jp(0x208),
+            0x8000
+        ]);
+        emu.run(4).unwrap();
+
+        assert_eq!(emu.display_dimensions(), (SCREEN_WIDTH, SCREEN_HEIGHT));
+        assert!(emu.pixel(10, 5));
+        assert!(!emu.pixel(11, 5));
+        assert!(!emu.pixel(10, 6));
+        assert!(!emu.pixel(SCREEN_WIDTH + 10, 5));
+        assert!(!emu.pixel(10, SCREEN_HEIGHT + 5));
+    }
+
+    // The first byte of each of the display's top rows as packed_display has it
+    fn left_column_bytes(emu: &Emu, rows: usize) -> Vec<u8> {
+        let row_bytes = emu.display_dimensions().0 / 8;
+        emu.packed_display().chunks(row_bytes).take(rows).map(|row| row[0]).collect()
+    }
+
+    #[test]
+    fn custom_small_font_is_drawn_through_fx29() {
+        let glyph = [0x81, 0x42, 0x24, 0x18, 0xFF];
+        let mut font = [0; FONTSET_SIZE];
+        font[3 * FONT_SIZE..4 * FONT_SIZE].copy_from_slice(&glyph);
+
+        let mut emu = EmuBuilder::new().seed(0).small_font(font).build();
+        emu.load(&asm::to_bytes(&[
+            asm::ld_vx_byte(0, 3),
+            asm::ld_f_vx(0),
+            asm::ld_vx_byte(1, 0),
+            asm::drw(1, 1, 5),
+            asm::jp(0x208)
+        ])).unwrap();
+        emu.run(4).unwrap();
+
+        assert_eq!(left_column_bytes(&emu, 6), [0x81, 0x42, 0x24, 0x18, 0xFF, 0x00]);
+    }
+
+    #[test]
+    fn custom_big_font_is_drawn_through_fx30() {
+        let glyph = [0xF0, 0x0F, 0xAA, 0x55, 0x01, 0x80, 0x3C, 0xC3, 0x66, 0x99];
+        let mut font = [0; BIG_FONTSET_SIZE];
+        font[2 * BIG_FONT_SIZE..3 * BIG_FONT_SIZE].copy_from_slice(&glyph);
+
+        let mut emu = EmuBuilder::new().seed(0).big_font(font).build();
+        emu.load(&asm::to_bytes(&[
+            asm::high(),
+            asm::ld_vx_byte(0, 2),
+            asm::ld_hf_vx(0),
+            asm::ld_vx_byte(1, 0),
+            asm::drw(1, 1, 10),
+            asm::jp(0x20A)
+        ])).unwrap();
+        emu.run(5).unwrap();
+
+        let mut expected = glyph.to_vec();
+        expected.push(0x00);
+        assert_eq!(left_column_bytes(&emu, 11), expected);
+    }
+
+    #[test]
+    fn hires_opcodes_switch_the_display_dimensions() {
+        let mut emu = emu_with(&[asm::high(), asm::low(), asm::high()]);
+        assert_eq!(emu.display_dimensions(), (SCREEN_WIDTH, SCREEN_HEIGHT));
+
+        emu.tick().unwrap();
+        assert!(emu.is_hires());
+        assert_eq!(emu.display_dimensions(), (HIRES_SCREEN_WIDTH, HIRES_SCREEN_HEIGHT));
+        assert_eq!(emu.get_display().len(), HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT);
+
+        emu.tick().unwrap();
+        assert!(!emu.is_hires());
+        assert_eq!(emu.display_dimensions(), (SCREEN_WIDTH, SCREEN_HEIGHT));
+        assert_eq!(emu.get_display().len(), SCREEN_WIDTH * SCREEN_HEIGHT);
+
+        emu.tick().unwrap();
+        assert_eq!(emu.display_dimensions(), (HIRES_SCREEN_WIDTH, HIRES_SCREEN_HEIGHT));
+    }
+
+    #[test]
+    fn reset_mid_key_wait_starts_the_next_wait_clean() {
+        let program = asm::to_bytes(&[asm::ld_vx_k(0), asm::jp(0x202)]);
+        let mut emu = emu_with(&[]);
+        emu.set_quirks(Quirks { wait_for_key_release: true, ..Quirks::default() });
+        emu.load(&program).unwrap();
+
+        // Key 5 goes down during the wait, then the machine is reset with it held
+        emu.keypress(5, true);
+        emu.tick().unwrap();
+        emu.reset_and_load(&program).unwrap();
+
+        // Key 5 being up now isn't a release the new wait saw
+        emu.tick().unwrap();
+        assert!(emu.is_waiting_for_key());
+        assert_eq!(emu.program_counter(), 0x200);
+
+        emu.keypress(7, true);
+        emu.tick().unwrap();
+        emu.keypress(7, false);
+        emu.tick().unwrap();
+        assert_eq!(emu.registers()[0], 7);
+        assert_eq!(emu.program_counter(), 0x202);
+    }
+
+    // Light (x, 5) and (x + 1, 5), in hires if asked, then run the given
+    // scroll and list the lit pixels
+    fn scrolled_dots(scroll: u16, hires: bool, x: u8) -> Vec<(usize, usize)> {
+        let mut emu = emu_with(&[
+            if hires { asm::high() } else { asm::cls() },
+            asm::ld_vx_byte(0, x),
+            asm::ld_vx_byte(1, 5),
+            asm::ld_i(0x20C),
+            asm::drw(0, 1, 1),
+            scroll,
+            0xC000
+        ]);
+        emu.run(6).unwrap();
+
+        emu.lit_pixels().collect()
+    }
+
+    #[test]
+    fn scroll_down_moves_the_display_down_n_rows() {
+        assert_eq!(scrolled_dots(asm::scd(3), false, 10), [(10, 8), (11, 8)]);
+        assert_eq!(scrolled_dots(asm::scd(3), true, 10), [(10, 8), (11, 8)]);
+        assert_eq!(scrolled_dots(asm::scd(0), false, 10), [(10, 5), (11, 5)]);
+    }
+
+    #[test]
+    fn scroll_right_moves_the_display_right_four_columns() {
+        assert_eq!(scrolled_dots(asm::scr(), false, 10), [(14, 5), (15, 5)]);
+        assert_eq!(scrolled_dots(asm::scr(), true, 120), [(124, 5), (125, 5)]);
+        assert_eq!(scrolled_dots(asm::scr(), false, 59), [(63, 5)]);
+    }
+
+    #[test]
+    fn scroll_left_moves_the_display_left_four_columns() {
+        assert_eq!(scrolled_dots(asm::scl(), false, 10), [(6, 5), (7, 5)]);
+        assert_eq!(scrolled_dots(asm::scl(), true, 100), [(96, 5), (97, 5)]);
+        assert_eq!(scrolled_dots(asm::scl(), false, 3), [(0, 5)]);
+    }
+
+    #[test]
+    fn run_until_halt_runs_a_program_to_its_end() {
+        // V2 = 3 + 4, then draw the digit 7 at (0, 0) and stop
+        let mut emu = emu_with(&[
+            asm::ld_vx_byte(0, 3),
+            asm::ld_vx_byte(1, 4),
+            asm::ld_vx_vy(2, 0),
+            asm::add_vx_vy(2, 1),
+            asm::ld_f_vx(2),
+            asm::ld_vx_byte(3, 0),
+            asm::drw(3, 3, 5),
+            asm::jp(0x20E)
+        ]);
+
+        assert_eq!(emu.run_until_halt(100), Ok(StopReason::Halted));
+        assert_eq!(emu.registers()[2], 7);
+        assert_eq!(emu.program_counter(), 0x20E);
+        assert_eq!(left_column_bytes(&emu, 5), FONTSET[7 * FONT_SIZE..8 * FONT_SIZE]);
+    }
+
+    #[test]
+    fn run_stops_at_each_draw_under_display_wait() {
+        let mut emu = emu_with(&[asm::drw(0, 0, 1), asm::drw(0, 0, 1), asm::add_vx_byte(1, 1), asm::jp(0x206)]);
+        emu.set_quirks(Quirks { display_wait: true, ..Quirks::default() });
+
+        emu.run(10).unwrap();
+        assert_eq!(emu.last_stop_reason(), Some(StopReason::WaitingForVblank));
+        assert_eq!(emu.program_counter(), 0x202);
+
+        // Running again without a frame goes nowhere
+        emu.run(10).unwrap();
+        assert_eq!(emu.program_counter(), 0x202);
+
+        emu.tick_timers();
+        assert_eq!(emu.run_until_halt(10), Ok(StopReason::WaitingForVblank));
+        assert_eq!(emu.program_counter(), 0x204);
+
+        emu.tick_timers();
+        assert_eq!(emu.run_until_halt(10), Ok(StopReason::Halted));
+        assert_eq!(emu.registers()[1], 1);
+    }
+
+    #[test]
+    fn load_state_rejects_another_state_version() {
+        let mut emu = emu_with(&[asm::ld_vx_byte(0, 1)]);
+        let mut state = emu.save_state();
+        state.version = STATE_VERSION - 1;
+
+        emu.tick().unwrap();
+        assert_eq!(
+            emu.load_state(&state),
+            Err(Chip8Error::IncompatibleStateVersion { found: STATE_VERSION - 1, expected: STATE_VERSION })
+        );
+        assert_eq!(emu.program_counter(), 0x202);
+
+        state.version = STATE_VERSION;
+        assert_eq!(emu.load_state(&state), Ok(()));
+        assert_eq!(emu.program_counter(), 0x200);
+    }
+
+    // The value export_octo_options gives the named Octo option under quirks
+    fn octo_option(quirks: Quirks, name: &str) -> bool {
+        let mut emu = Emu::with_seed(0);
+        emu.set_quirks(quirks);
+
+        let options = emu.export_octo_options();
+        let line = options.lines()
+            .find(|line| line.trim_start().starts_with(&format!("\"{}\":", name)))
+            .unwrap_or_else(|| panic!("no {} in {}", name, options));
+
+        line.trim_end_matches(',').ends_with("true")
+    }
+
+    #[test]
+    fn octo_options_for_the_default_quirks() {
+        assert_eq!(Emu::with_seed(0).export_octo_options(), "{
+  \"shiftQuirks\": true,
+  \"loadStoreQuirks\": false,
+  \"jumpQuirks\": false,
+  \"vfOrderQuirks\": false,
+  \"logicQuirks\": false,
+  \"clipQuirks\": false,
+  \"vBlankQuirks\": false
+}");
+    }
+
+    #[test]
+    fn each_quirk_maps_to_its_octo_option() {
+        let default = Quirks::default();
+
+        assert!(!octo_option(Quirks { shift_uses_vy: true, ..default }, "shiftQuirks"));
+        assert!(octo_option(Quirks { load_store_increments_i: false, ..default }, "loadStoreQuirks"));
+        assert!(octo_option(Quirks { jump_uses_vx: true, ..default }, "jumpQuirks"));
+        assert!(octo_option(Quirks { clip_sprites: true, ..default }, "clipQuirks"));
+        assert!(octo_option(Quirks { display_wait: true, ..default }, "vBlankQuirks"));
+    }
+
+    #[test]
+    fn reset_puts_the_font_back() {
+        let mut emu = emu_with(&[asm::ld_vx_byte(0, 9)]);
+        emu.tick().unwrap();
+        emu.reset();
+
+        emu.execute_opcode(asm::ld_f_vx(0)).unwrap();
+        let i = emu.i_register() as usize;
+        assert_eq!(emu.ram[i..i + FONT_SIZE], FONTSET[..FONT_SIZE]);
+    }
+
+    #[test]
+    fn reset_and_load_runs_the_new_rom_from_the_start() {
+        let mut emu = emu_with(&[asm::ld_vx_byte(0, 9), asm::ld_vx_byte(1, 9)]);
+        emu.run(2).unwrap();
+
+        emu.reset_and_load(&asm::to_bytes(&[asm::ld_vx_byte(2, 5)])).unwrap();
+        assert_eq!(emu.program_counter(), START_ADDR);
+        assert_eq!(emu.registers()[..3], [0, 0, 0]);
+        assert_eq!(emu.ram[START_ADDR as usize + 2..START_ADDR as usize + 4], [0, 0]);
+
+        emu.tick().unwrap();
+        assert_eq!(emu.registers()[2], 5);
+    }
+
+    #[test]
+    fn overlapping_sprites_report_the_erased_pixels() {
+        // 0xF0 at (4, 2), then 0x3C at (4, 2) erases the middle two pixels
+        let mut emu = emu_with(&[
+            asm::ld_vx_byte(0, 4),
+            asm::ld_vx_byte(1, 2),
+            asm::ld_i(0x210),
+            asm::drw(0, 1, 1),
+            asm::ld_i(0x211),
+            asm::drw(0, 1, 1),
+            asm::jp(0x20C),
+            0x0000,
+            0xF03C
+        ]);
+        emu.run(6).unwrap();
+
+        assert_eq!(emu.collision_pixels(), [(6, 2), (7, 2)]);
+
+        emu.tick_timers();
+        assert!(emu.collision_pixels().is_empty());
+    }
+
+    #[test]
+    fn collision_pixels_stop_growing_at_the_cap() {
+        // Keep drawing the 8x15 block of 0xFF bytes at 0x20A over itself.
+        // Every other draw erases its 120 pixels, four ticks apart
+        let mut program = vec![asm::ld_i(0x20A), asm::drw(0, 0, 15), asm::jp(0x202), 0x0000, 0x0000];
+        program.extend([0xFFFF; 8]);
+        let mut emu = emu_with(&program);
+
+        emu.run(4 * 2 * MAX_COLLISION_PIXELS / 120).unwrap();
+        assert_eq!(emu.collision_pixels().len(), MAX_COLLISION_PIXELS);
+    }
+
+    #[test]
+    fn out_of_range_keys_are_ignored() {
+        let mut emu = emu_with(&[]);
+
+        emu.keypress(99, true);
+        emu.keypress(NUM_KEYS, true);
+        assert!(!emu.key_state(99));
+        assert!(!emu.key_state(NUM_KEYS));
+        assert_eq!(emu.keypad(), &[false; NUM_KEYS]);
+
+        emu.keypress(0xF, true);
+        assert!(emu.key_state(0xF));
+    }
+
+    // A jump to 0x203, where LD V0, 0x2A sits on an odd address
+    fn odd_address_program() -> Emu {
+        let mut rom = asm::to_bytes(&[asm::jp(0x203)]);
+        rom.push(0x00);
+        rom.extend(asm::to_bytes(&[asm::ld_vx_byte(0, 0x2A)]));
+
+        let mut emu = Emu::with_seed(0);
+        emu.load(&rom).unwrap();
+        emu
+    }
+
+    #[test]
+    fn odd_addresses_run_by_default() {
+        let mut emu = odd_address_program();
+
+        emu.run(2).unwrap();
+        assert_eq!(emu.registers()[0], 0x2A);
+        assert_eq!(emu.program_counter(), 0x205);
+    }
+
+    #[test]
+    fn odd_addresses_are_refused_under_strict_alignment() {
+        let mut emu = odd_address_program();
+        emu.set_quirks(Quirks { strict_alignment: true, ..Quirks::default() });
+
+        emu.tick().unwrap();
+        assert_eq!(emu.tick(), Err(Chip8Error::MisalignedPc(0x203)));
+        assert_eq!(emu.registers()[0], 0);
+    }
+
+    #[test]
+    fn load_at_puts_the_rom_and_pc_at_the_start_address() {
+        let mut emu = Emu::with_seed(0);
+
+        assert_eq!(emu.load_at(&[0x60, 0x2A, 0x71, 0x01], 0x600), Ok(None));
+        assert_eq!(emu.ram[0x600..0x604], [0x60, 0x2A, 0x71, 0x01]);
+        assert_eq!(emu.ram[0x200..0x204], [0, 0, 0, 0]);
+        assert_eq!(emu.program_counter(), 0x600);
+
+        emu.run(2).unwrap();
+        assert_eq!(emu.registers()[0], 0x2A);
+        assert_eq!(emu.registers()[1], 0x01);
+    }
+
+    #[test]
+    fn load_at_rejects_a_start_past_the_end_of_ram() {
+        let mut emu = Emu::with_seed(0);
+
+        assert_eq!(emu.load_at(&[], 0x2000), Err(LoadError::StartOutOfBounds(0x2000)));
+        assert_eq!(emu.load_at(&[0x00], RAM_SIZE as u16), Err(LoadError::StartOutOfBounds(0x1000)));
+        assert_eq!(emu.load_at(&[0x00; 3], 0xFFE), Err(LoadError::TooLarge { len: 3, max: 2 }));
+        assert_eq!(emu.program_counter(), START_ADDR);
+    }
+
+    #[test]
+    fn empty_sprite_clears_vf() {
+        // Two draws of the blank row at 0x20A, the second over the first, with VF set beforehand
+        let mut emu = emu_with(&[
+            asm::ld_vx_byte(0xF, 1),
+            asm::ld_i(0x20A),
+            asm::drw(0, 0, 1),
+            asm::drw(0, 0, 1),
+            asm::jp(0x208),
+            0x0000
+        ]);
+
+        emu.run(3).unwrap();
+        assert_eq!(emu.registers()[0xF], 0);
+        emu.tick().unwrap();
+        assert_eq!(emu.registers()[0xF], 0);
+        assert_eq!(emu.lit_pixels().count(), 0);
+    }
+
+    #[test]
+    fn zero_row_sprite_draws_nothing_in_low_resolution() {
+        let mut emu = emu_with(&[asm::ld_vx_byte(0xF, 1), asm::ld_i(0x208), asm::drw(0, 0, 0), asm::jp(0x206), 0xFFFF]);
+
+        emu.run(3).unwrap();
+        assert_eq!(emu.registers()[0xF], 0);
+        assert_eq!(emu.lit_pixels().count(), 0);
+    }
+
+    #[test]
+    fn zero_row_sprite_is_sixteen_by_sixteen_in_high_resolution() {
+        let mut program = vec![asm::high(), asm::ld_i(0x20A), asm::drw(0, 0, 0), asm::drw(0, 0, 0), asm::jp(0x208)];
+        program.extend([0xFFFF; 16]);
+        let mut emu = emu_with(&program);
+
+        emu.run(3).unwrap();
+        assert_eq!(emu.registers()[0xF], 0);
+        let lit: Vec<_> = emu.lit_pixels().collect();
+        assert_eq!(lit.len(), 16 * 16);
+        assert_eq!(lit.last(), Some(&(15, 15)));
+
+        // Drawing it again erases all of it
+        emu.tick().unwrap();
+        assert_eq!(emu.registers()[0xF], 1);
+        assert_eq!(emu.lit_pixels().count(), 0);
+    }
+
+    // A random byte into each of V0 to V7
+    fn rnd_program() -> Vec<u8> {
+        asm::to_bytes(&(0..8).map(|x| asm::rnd(x, 0xFF)).collect::<Vec<_>>())
+    }
+
+    // V0 to V7 after rnd_program on an emulator seeded with seed
+    fn random_registers(seed: u64) -> Vec<u8> {
+        let mut emu = Emu::with_seed(seed);
+        emu.load(&rnd_program()).unwrap();
+        emu.run(8).unwrap();
+
+        emu.registers()[..8].to_vec()
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_random_numbers() {
+        assert_eq!(random_registers(42), random_registers(42));
+        assert_ne!(random_registers(42), random_registers(43));
+    }
+
+    #[test]
+    fn reseed_starts_the_sequence_over() {
+        let mut emu = Emu::with_seed(7);
+        emu.load(&rnd_program()).unwrap();
+        emu.run(8).unwrap();
+
+        emu.reset();
+        emu.reseed(42);
+        emu.load(&rnd_program()).unwrap();
+        emu.run(8).unwrap();
+        assert_eq!(emu.registers()[..8], random_registers(42));
+    }
+
+    #[test]
+    fn reading_vf_as_vy_traps_when_asked() {
+        let mut emu = emu_with(&[asm::ld_vx_byte(0xF, 3), asm::ld_vx_vy(0, 0xF)]);
+        emu.set_trap_vf_reads(true);
+
+        emu.tick().unwrap();
+        assert_eq!(emu.tick(), Err(Chip8Error::VfRead(0x80F0)));
+        assert_eq!(emu.program_counter(), 0x202);
+        assert_eq!(emu.registers()[0], 0);
+    }
+
+    #[test]
+    fn reading_vf_as_vy_is_allowed_by_default() {
+        let mut emu = emu_with(&[asm::ld_vx_byte(0xF, 3), asm::ld_vx_vy(0, 0xF), asm::se_vx_byte(0xF, 1)]);
+
+        emu.run(2).unwrap();
+        assert_eq!(emu.registers()[0], 3);
+
+        // Flag tests aren't trapped either way
+        emu.set_trap_vf_reads(true);
+        assert_eq!(emu.tick(), Ok(()));
+    }
+
+    // Draw an 8x2 block at (60, 31), hanging off the right and bottom edges,
+    // and list the lit pixels
+    fn edge_draw(clip_sprites: bool) -> Vec<(usize, usize)> {
+        let mut emu = emu_with(&[
+            asm::ld_vx_byte(0, 60),
+            asm::ld_vx_byte(1, 31),
+            asm::ld_i(0x20A),
+            asm::drw(0, 1, 2),
+            asm::jp(0x208),
+            0xFFFF
+        ]);
+        emu.set_quirks(Quirks { clip_sprites, ..Quirks::default() });
+        emu.run(4).unwrap();
+
+        emu.lit_pixels().collect()
+    }
+
+    #[test]
+    fn sprites_wrap_around_the_edges_by_default() {
+        let mut expected: Vec<_> = (0..4).chain(60..64).map(|x| (x, 0)).collect();
+        expected.extend((0..4).chain(60..64).map(|x| (x, 31)));
+
+        assert_eq!(edge_draw(false), expected);
+    }
+
+    #[test]
+    fn sprites_are_cut_off_at_the_edges_when_clipped() {
+        assert_eq!(edge_draw(true), (60..64).map(|x| (x, 31)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn display_wait_holds_ticks_until_the_next_frame() {
+        let mut emu = emu_with(&[asm::drw(0, 0, 1), asm::add_vx_byte(1, 1), asm::add_vx_byte(1, 1)]);
+        emu.set_quirks(Quirks { display_wait: true, ..Quirks::default() });
+
+        emu.tick().unwrap();
+        assert!(emu.is_waiting_for_vblank());
+        emu.tick().unwrap();
+        emu.tick().unwrap();
+        assert_eq!(emu.registers()[1], 0);
+        assert_eq!(emu.program_counter(), 0x202);
+
+        emu.tick_timers();
+        assert!(!emu.is_waiting_for_vblank());
+        emu.tick().unwrap();
+        assert_eq!(emu.registers()[1], 1);
+    }
+
+    #[test]
+    fn draws_dont_wait_without_display_wait() {
+        let mut emu = emu_with(&[asm::drw(0, 0, 1), asm::add_vx_byte(1, 1), asm::add_vx_byte(1, 1)]);
+
+        emu.tick().unwrap();
+        assert!(!emu.is_waiting_for_vblank());
+        emu.run(2).unwrap();
+        assert_eq!(emu.registers()[1], 2);
+    }
+
+    #[test]
+    fn fonts_start_the_reserved_region() {
+        let emu = Emu::with_seed(0);
+        let reserved = emu.reserved_region();
+
+        assert_eq!(reserved.len(), RESERVED_SIZE);
+        assert_eq!(reserved[..FONTSET_SIZE], FONTSET);
+        assert_eq!(reserved[FONTSET_SIZE..FONTSET_SIZE + BIG_FONTSET_SIZE], BIG_FONTSET);
+        assert!(reserved[FONTSET_SIZE + BIG_FONTSET_SIZE..].iter().all(|b| *b == 0));
+    }
+
+    // VF and I after FX1E adds v0 to I = i, with VF set to 2 beforehand
+    fn add_i_result(add_i_overflow_sets_vf: bool, i: u16, v0: u8) -> (u8, u16) {
+        let mut emu = emu_with(&[asm::ld_i(i), asm::ld_vx_byte(0, v0), asm::ld_vx_byte(0xF, 2), asm::add_i_vx(0)]);
+        emu.set_quirks(Quirks { add_i_overflow_sets_vf, ..Quirks::default() });
+        emu.run(4).unwrap();
+
+        (emu.registers()[0xF], emu.i_register())
+    }
+
+    #[test]
+    fn add_i_leaves_vf_alone_by_default() {
+        assert_eq!(add_i_result(false, 0xFFE, 0x05), (2, 0x1003));
+        assert_eq!(add_i_result(false, 0x200, 0x05), (2, 0x205));
+    }
+
+    #[test]
+    fn add_i_flags_going_past_twelve_bits_when_asked() {
+        assert_eq!(add_i_result(true, 0xFFE, 0x05), (1, 0x1003));
+        assert_eq!(add_i_result(true, 0xFFE, 0x01), (0, 0xFFF));
+        assert_eq!(add_i_result(true, 0x200, 0x05), (0, 0x205));
+    }
+
+    // An emulator under the policy with a full stack of 0x300, 0x302, ...
+    fn full_stack(stack_overflow: StackOverflowPolicy) -> Emu {
+        let mut emu = emu_with(&[]);
+        emu.set_quirks(Quirks { stack_overflow, ..Quirks::default() });
+        for n in 0..STACK_SIZE as u16 {
+            emu.push(0x300 + 2 * n).unwrap();
+        }
+
+        assert_eq!(emu.stack_pointer(), STACK_SIZE as u16);
+        emu
+    }
+
+    #[test]
+    fn push_onto_a_full_stack_errors_by_default() {
+        let mut emu = full_stack(StackOverflowPolicy::Error);
+
+        assert_eq!(emu.push(0x400), Err(Chip8Error::StackOverflow));
+        assert_eq!(emu.stack_pointer(), STACK_SIZE as u16);
+        assert_eq!(emu.pop(), Ok(0x31E));
+    }
+
+    #[test]
+    fn push_onto_a_full_stack_is_dropped_when_saturating() {
+        let mut emu = full_stack(StackOverflowPolicy::Saturate);
+
+        assert_eq!(emu.push(0x400), Ok(()));
+        assert_eq!(emu.stack_pointer(), STACK_SIZE as u16);
+        assert_eq!(emu.pop(), Ok(0x31E));
+    }
+
+    #[test]
+    fn push_onto_a_full_stack_overwrites_the_bottom_when_wrapping() {
+        let mut emu = full_stack(StackOverflowPolicy::Wrap);
+
+        assert_eq!(emu.push(0x400), Ok(()));
+        assert_eq!(emu.stack_pointer(), 1);
+        assert_eq!(emu.export_stack().0[..2], [0x400, 0x302]);
+        assert_eq!(emu.pop(), Ok(0x400));
+    }
+
+    #[test]
+    fn restored_rng_state_repeats_the_random_numbers() {
+        let mut emu = Emu::with_seed(3);
+        emu.load(&rnd_program()).unwrap();
+        let rng = emu.rng_state();
+        emu.run(8).unwrap();
+        let first = emu.registers()[..8].to_vec();
+
+        emu.reset();
+        emu.load(&rnd_program()).unwrap();
+        emu.set_rng_state(&rng).unwrap();
+        emu.run(8).unwrap();
+        assert_eq!(emu.registers()[..8], first);
+    }
+
+    #[test]
+    fn restored_save_state_repeats_the_random_numbers() {
+        let mut emu = Emu::with_seed(3);
+        emu.load(&rnd_program()).unwrap();
+        emu.run(4).unwrap();
+        let state = emu.save_state();
+        emu.run(4).unwrap();
+        let first = emu.registers()[..8].to_vec();
+
+        emu.load_state(&state).unwrap();
+        emu.run(4).unwrap();
+        assert_eq!(emu.registers()[..8], first);
+    }
+
+    #[test]
+    fn set_rng_state_rejects_a_malformed_state() {
+        let mut emu = Emu::with_seed(3);
+
+        assert_eq!(emu.set_rng_state(&[0; 5]), Err(Chip8Error::InvalidState));
+    }
+
+    // VF after drawing the sprite bytes at 0x20A, then the ones at 0x210, both
+    // at (0, 0). Each sprite is three rows
+    fn vf_after_draws(first: [u8; 3], second: [u8; 3]) -> (u8, u8) {
+        let mut rom = asm::to_bytes(&[
+            asm::ld_i(0x20A),
+            asm::drw(0, 0, 3),
+            asm::ld_i(0x210),
+            asm::drw(0, 0, 3),
+            asm::jp(0x208)
+        ]);
+        rom.extend(first);
+        rom.extend([0; 3]);
+        rom.extend(second);
+
+        let mut emu = Emu::with_seed(0);
+        emu.load(&rom).unwrap();
+        emu.run(2).unwrap();
+        let vf_first = emu.registers()[0xF];
+        emu.run(2).unwrap();
+
+        (vf_first, emu.registers()[0xF])
+    }
+
+    #[test]
+    fn clean_draw_clears_vf() {
+        assert_eq!(vf_after_draws([0xF0, 0x00, 0x00], [0x0F, 0xFF, 0x00]), (0, 0));
+    }
+
+    #[test]
+    fn overlapping_draw_sets_vf() {
+        assert_eq!(vf_after_draws([0xF0, 0x00, 0x00], [0x10, 0x00, 0x00]), (0, 1));
+    }
+
+    #[test]
+    fn collision_on_the_third_row_sets_vf() {
+        assert_eq!(vf_after_draws([0x00, 0x00, 0x01], [0xFF, 0xFF, 0x01]), (0, 1));
+    }
+
+    #[test]
+    fn vf_is_cleared_by_a_draw_after_a_collision() {
+        // Three draws of the single pixel at 0x20A
+        let mut emu = emu_with(&[
+            asm::ld_i(0x20A),
+            asm::drw(0, 0, 1),
+            asm::drw(0, 0, 1),
+            asm::drw(0, 0, 1),
+            asm::jp(0x208),
+            0x8000
+        ]);
+
+        emu.run(3).unwrap();
+        assert_eq!(emu.registers()[0xF], 1);
+        emu.tick().unwrap();
+        assert_eq!(emu.registers()[0xF], 0);
+    }
+
+    #[test]
+    fn trace_collects_the_opcodes_run() {
+        let program = [asm::ld_vx_byte(0, 1), asm::call(0x206), asm::jp(0x204), asm::ret()];
+        let mut emu = emu_with(&program);
+        let traced = Arc::new(Mutex::new(Vec::new()));
+        let log = traced.clone();
+        emu.set_trace_callback(Some(Box::new(move |_, op| log.lock().unwrap().push(op))));
+
+        emu.run(4).unwrap();
+        assert_eq!(*traced.lock().unwrap(), [program[0], program[1], program[3], program[2]]);
+
+        // Clearing the callback stops the trace
+        emu.set_trace_callback(None);
+        emu.run(2).unwrap();
+        assert_eq!(traced.lock().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn emulator_with_a_trace_can_move_to_another_thread() {
+        let mut emu = emu_with(&[asm::ld_vx_byte(0, 1)]);
+        emu.set_trace_callback(Some(Box::new(|_, _| ())));
+
+        let emu = std::thread::spawn(move || {
+            emu.tick().unwrap();
+            emu
+        }).join().unwrap();
+        assert_eq!(emu.registers()[0], 1);
+    }
+
+    #[test]
+    fn written_ram_reads_back() {
+        let mut emu = Emu::with_seed(0);
+
+        assert_eq!(emu.write_ram(0x300, 0xAB), Ok(()));
+        assert_eq!(emu.read_ram(0x300), Some(0xAB));
+        assert_eq!(emu.write_ram(0xFFF, 0xCD), Ok(()));
+        assert_eq!(emu.read_ram(0xFFF), Some(0xCD));
+    }
+
+    #[test]
+    fn ram_accessors_reject_addresses_past_the_end() {
+        let mut emu = Emu::with_seed(0);
+
+        assert_eq!(emu.read_ram(0x1000), None);
+        assert_eq!(emu.write_ram(0x1000, 0xAB), Err(Chip8Error::AddressOutOfBounds(0x1000)));
+        assert_eq!(emu.read_ram(0xFFFF), None);
+    }
+
+    // V1 and VF after op runs on V1 = a and V2 = b
+    fn sub_result(op: u16, a: u8, b: u8) -> (u8, u8) {
+        let mut emu = emu_with(&[asm::ld_vx_byte(1, a), asm::ld_vx_byte(2, b), op]);
+        emu.run(3).unwrap();
+
+        (emu.registers()[1], emu.registers()[0xF])
+    }
+
+    #[test]
+    fn sub_of_equal_values_doesnt_borrow() {
+        assert_eq!(sub_result(asm::sub(1, 2), 7, 7), (0, 1));
+        assert_eq!(sub_result(asm::sub(1, 2), 9, 7), (2, 1));
+        assert_eq!(sub_result(asm::sub(1, 2), 7, 9), (254, 0));
+    }
+
+    #[test]
+    fn subn_of_equal_values_doesnt_borrow() {
+        assert_eq!(sub_result(asm::subn(1, 2), 7, 7), (0, 1));
+        assert_eq!(sub_result(asm::subn(1, 2), 7, 9), (2, 1));
+        assert_eq!(sub_result(asm::subn(1, 2), 9, 7), (254, 0));
+    }
+
+    #[test]
+    fn key_release_wait_finishes_when_the_key_is_let_go() {
+        let mut emu = emu_with(&[asm::ld_vx_k(0), asm::jp(0x202)]);
+        emu.set_quirks(Quirks { wait_for_key_release: true, ..Quirks::default() });
+
+        emu.keypress(0xA, true);
+        emu.run(3).unwrap();
+        assert!(emu.is_waiting_for_key());
+        assert_eq!(emu.program_counter(), 0x200);
+
+        emu.keypress(0xA, false);
+        emu.tick().unwrap();
+        assert_eq!(emu.registers()[0], 0xA);
+        assert_eq!(emu.program_counter(), 0x202);
+    }
+
+    #[test]
+    fn key_release_wait_takes_the_last_key_let_go() {
+        let mut emu = emu_with(&[asm::ld_vx_k(0), asm::jp(0x202)]);
+        emu.set_quirks(Quirks { wait_for_key_release: true, ..Quirks::default() });
+
+        emu.keypress(3, true);
+        emu.keypress(5, true);
+        emu.tick().unwrap();
+        emu.keypress(3, false);
+        emu.tick().unwrap();
+        assert_eq!(emu.program_counter(), 0x200);
+
+        emu.keypress(5, false);
+        emu.tick().unwrap();
+        assert_eq!(emu.registers()[0], 5);
+    }
+
+    #[test]
+    fn key_wait_finishes_on_the_press_by_default() {
+        let mut emu = emu_with(&[asm::ld_vx_k(0), asm::jp(0x202)]);
+
+        emu.tick().unwrap();
+        assert_eq!(emu.program_counter(), 0x200);
+
+        emu.keypress(0xA, true);
+        emu.tick().unwrap();
+        assert_eq!(emu.registers()[0], 0xA);
+        assert_eq!(emu.program_counter(), 0x202);
+    }
+
+    #[test]
+    fn large_rom_loads_into_a_64k_machine() {
+        let mut rom = vec![0; 6000];
+        rom[5998..].copy_from_slice(&asm::ld_vx_byte(0, 0x2A).to_be_bytes());
+        let mut emu = Emu::with_memory_size(MAX_RAM_SIZE);
+
+        assert_eq!(emu.load(&rom), Ok(None));
+        assert_eq!(emu.ram.len(), MAX_RAM_SIZE);
+        assert_eq!(emu.read_ram(START_ADDR + 5999), Some(0x2A));
+
+        emu.pc = START_ADDR + 5998;
+        emu.tick().unwrap();
+        assert_eq!(emu.registers()[0], 0x2A);
+    }
+
+    #[test]
+    fn stats_count_the_jumps_of_a_loop() {
+        // Five times round a loop of ADD, SE, and JP, then the halting jump
+        let mut emu = emu_with(&[asm::add_vx_byte(0, 1), asm::se_vx_byte(0, 5), asm::jp(0x200), asm::jp(0x206)]);
+        assert_eq!(emu.run_until_halt(100), Ok(StopReason::Halted));
+
+        let stats = emu.instruction_stats();
+        assert_eq!(stats.count(0x1), 5);
+        assert_eq!(stats.count(0x7), 5);
+        assert_eq!(stats.count(0x3), 5);
+        assert_eq!(stats.total(), 15);
+
+        emu.reset_stats();
+        assert_eq!(emu.instruction_stats().total(), 0);
+    }
+
+    #[test]
+    fn jump_to_itself_halts() {
+        let mut emu = emu_with(&[asm::ld_vx_byte(0, 1), asm::jp(0x202)]);
+
+        emu.tick().unwrap();
+        assert!(!emu.is_halted());
+        emu.tick().unwrap();
+        assert!(emu.is_halted());
+        assert_eq!(emu.program_counter(), 0x202);
+    }
+
+    #[test]
+    fn jump_elsewhere_doesnt_halt() {
+        let mut emu = emu_with(&[asm::jp(0x202), asm::jp(0x200)]);
+
+        emu.run(10).unwrap();
+        assert!(!emu.is_halted());
+    }
+
+    #[test]
+    fn scroll_up_moves_the_display_up_n_rows() {
+        // Light (10, 30) and (11, 30), then (10, 31), near the bottom
+        let mut emu = emu_with(&[
+            asm::ld_vx_byte(0, 10),
+            asm::ld_vx_byte(1, 30),
+            asm::ld_i(0x20C),
+            asm::drw(0, 1, 2),
+            asm::scu(2),
+            asm::jp(0x20A),
+            0xC080
+        ]);
+        emu.run(5).unwrap();
+
+        assert_eq!(emu.lit_pixels().collect::<Vec<_>>(), [(10, 28), (11, 28), (10, 29)]);
+    }
+
+    // VF and V1 after op runs on V1 = v1 and VF = vf
+    fn vf_operand_result(op: u16, v1: u8, vf: u8) -> (u8, u8) {
+        let mut emu = emu_with(&[asm::ld_vx_byte(1, v1), asm::ld_vx_byte(0xF, vf), op]);
+        emu.run(3).unwrap();
+
+        (emu.registers()[0xF], emu.registers()[1])
+    }
+
+    #[test]
+    fn flag_overwrites_the_result_when_vx_is_vf() {
+        // 0x80 + 0x80 carries, and the flag wins over the sum of 0
+        assert_eq!(vf_operand_result(asm::add_vx_vy(0xF, 0xF), 0, 0x80), (1, 0));
+        // 5 - 3 doesn't borrow, so VF is 1 rather than the difference
+        assert_eq!(vf_operand_result(asm::sub(0xF, 1), 3, 5), (1, 3));
+        assert_eq!(vf_operand_result(asm::sub(0xF, 1), 5, 3), (0, 5));
+        assert_eq!(vf_operand_result(asm::subn(0xF, 1), 5, 3), (1, 5));
+    }
+
+    #[test]
+    fn old_vf_is_used_when_vy_is_vf() {
+        assert_eq!(vf_operand_result(asm::add_vx_vy(1, 0xF), 0xFF, 2), (1, 0x01));
+        assert_eq!(vf_operand_result(asm::sub(1, 0xF), 2, 3), (0, 0xFF));
+        assert_eq!(vf_operand_result(asm::subn(1, 0xF), 2, 3), (1, 0x01));
+    }
+
+    #[test]
+    fn logic_ops_on_vf_keep_their_result() {
+        assert_eq!(vf_operand_result(asm::or(0xF, 1), 0x0C, 0x03), (0x0F, 0x0C));
+        assert_eq!(vf_operand_result(asm::and(1, 0xF), 0x0C, 0x06), (0x06, 0x04));
+        assert_eq!(vf_operand_result(asm::ld_vx_vy(1, 0xF), 0, 0x2A), (0x2A, 0x2A));
+    }
+
+    #[test]
+    fn execute_opcode_runs_arithmetic() {
+        let mut emu = Emu::with_seed(0);
+        emu.execute_opcode(asm::ld_vx_byte(1, 0xF0)).unwrap();
+        emu.execute_opcode(asm::ld_vx_byte(2, 0x20)).unwrap();
+
+        emu.execute_opcode(0x8124).unwrap();
+        assert_eq!(emu.registers()[1], 0x10);
+        assert_eq!(emu.registers()[0xF], 1);
+        assert_eq!(emu.program_counter(), START_ADDR);
+    }
+
+    #[test]
+    fn execute_opcode_skips_past_the_instruction_at_pc() {
+        let mut emu = Emu::with_seed(0);
+        emu.execute_opcode(asm::ld_vx_byte(3, 0x2A)).unwrap();
+
+        emu.execute_opcode(asm::se_vx_byte(3, 0x2A)).unwrap();
+        assert_eq!(emu.program_counter(), START_ADDR + 2);
+        emu.execute_opcode(asm::se_vx_byte(3, 0x2B)).unwrap();
+        assert_eq!(emu.program_counter(), START_ADDR + 2);
+
+        emu.execute_opcode(asm::jp(0x300)).unwrap();
+        assert_eq!(emu.program_counter(), 0x300);
+    }
+
+    // Lit pixel counts of both planes after drawing the 2-row sprite at 0x20A
+    // with the given planes selected. Both planes get a copy when both are
+    // selected, the first 0xFF rows and the second 0x80 rows
+    fn plane_pixels(planes: u8) -> (usize, usize) {
+        let mut emu = emu_with(&[
+            asm::plane(planes),
+            asm::ld_i(0x20A),
+            asm::drw(0, 0, 2),
+            asm::jp(0x206),
+            0x0000,
+            0xFFFF,
+            0x8080
+        ]);
+        emu.run(3).unwrap();
+
+        let lit = |plane| emu.get_plane(plane).iter().filter(|pixel| **pixel).count();
+        (lit(0), lit(1))
+    }
+
+    #[test]
+    fn drawing_on_the_second_plane_leaves_the_first_untouched() {
+        assert_eq!(plane_pixels(2), (0, 16));
+    }
+
+    #[test]
+    fn drawing_on_both_planes_uses_a_sprite_each() {
+        assert_eq!(plane_pixels(1), (16, 0));
+        assert_eq!(plane_pixels(3), (16, 2));
+    }
+
+    #[test]
+    fn audio_pattern_and_pitch_start_at_their_defaults() {
+        let emu = Emu::with_seed(0);
+
+        assert!(!emu.has_audio_pattern());
+        assert_eq!(emu.audio_pattern(), &[0; AUDIO_PATTERN_SIZE]);
+        assert_eq!(emu.audio_pitch(), DEFAULT_PITCH);
+    }
+
+    #[test]
+    fn f002_and_fx3a_store_the_pattern_and_pitch() {
+        let mut program = vec![asm::ld_i(0x20A), asm::audio(), asm::ld_vx_byte(0, 112), asm::pitch(0), asm::jp(0x208)];
+        program.extend((0..8).map(|n| 0x1100 * n + 0x0F));
+        let mut emu = emu_with(&program);
+        emu.run(4).unwrap();
+
+        let pattern: Vec<u8> = (0..8u8).flat_map(|n| [0x11 * n, 0x0F]).collect();
+        assert!(emu.has_audio_pattern());
+        assert_eq!(emu.audio_pattern()[..], pattern[..]);
+        assert_eq!(emu.audio_pitch(), 112);
+    }
+
+    #[test]
+    fn waiting_for_key_is_set_by_fx0a_with_no_keys() {
+        let mut emu = emu_with(&[asm::ld_vx_k(0), asm::jp(0x202)]);
+        assert!(!emu.is_waiting_for_key());
+
+        emu.tick().unwrap();
+        assert!(emu.is_waiting_for_key());
+
+        emu.keypress(4, true);
+        emu.tick().unwrap();
+        assert!(!emu.is_waiting_for_key());
+        assert_eq!(emu.registers()[0], 4);
+    }
+
+    #[test]
+    fn long_ld_i_at_the_top_of_4k_memory() {
+        // F000 at 0xFFA with its address word at 0xFFC
+        let mut emu = Emu::with_seed(0);
+        emu.write_ram(0xFFA, 0xF0).unwrap();
+        emu.write_ram(0xFFB, 0x00).unwrap();
+        emu.write_ram(0xFFC, 0x02).unwrap();
+        emu.write_ram(0xFFD, 0x40).unwrap();
+        emu.pc = 0xFFA;
+
+        emu.tick().unwrap();
+        assert_eq!(emu.i_register(), 0x0240);
+        assert_eq!(emu.program_counter(), 0xFFE);
+    }
+
+    #[test]
+    fn skip_over_long_ld_i_skips_its_address_word_too() {
+        let mut emu = emu_with(&[asm::se_vx_byte(0, 0), asm::ld_i_long(), 0x1234, asm::ld_vx_byte(1, 1)]);
+
+        emu.tick().unwrap();
+        assert_eq!(emu.program_counter(), 0x206);
+        emu.tick().unwrap();
+        assert_eq!(emu.i_register(), 0);
+        assert_eq!(emu.registers()[1], 1);
+    }
+
+    #[test]
+    fn display_dirty_is_set_by_a_draw_and_cleared_when_taken() {
+        let mut emu = emu_with(&[asm::ld_vx_byte(0, 1), asm::drw(0, 0, 5), asm::cls()]);
+
+        // A new emulator starts out dirty so its first frame is drawn
+        assert!(emu.take_display_dirty());
+        assert!(!emu.take_display_dirty());
+
+        emu.tick().unwrap();
+        assert!(!emu.take_display_dirty());
+
+        emu.tick().unwrap();
+        assert!(emu.take_display_dirty());
+        assert!(!emu.take_display_dirty());
+
+        emu.tick().unwrap();
+        assert!(emu.take_display_dirty());
+    }
+
+    #[test]
+    fn odd_length_rom_loads_with_a_warning() {
+        let mut emu = Emu::with_seed(0);
+
+        assert_eq!(emu.load(&[0xAB; 51]), Ok(Some(LoadWarning::OddLength(51))));
+        assert_eq!(emu.read_ram(START_ADDR + 50), Some(0xAB));
+        assert_eq!(emu.load(&[0xAB; 52]), Ok(None));
+    }
+
+    // The error from running op at 0x202 with I = 0xFFE, two bytes from the end of RAM
+    fn error_near_the_end_of_ram(op: u16) -> Chip8Error {
+        let mut emu = emu_with(&[asm::ld_i(0xFFE), op]);
+        emu.tick().unwrap();
+
+        let e = emu.tick().unwrap_err();
+        assert_eq!(emu.read_ram(0xFFE), Some(0), "{:#06x} wrote to RAM before failing", op);
+        e
+    }
+
+    #[test]
+    fn out_of_bounds_draw_is_an_error() {
+        assert_eq!(error_near_the_end_of_ram(asm::drw(0, 0, 5)), Chip8Error::MemoryOutOfBounds { pc: 0x202, addr: 0x1000 });
+    }
+
+    #[test]
+    fn out_of_bounds_bcd_is_an_error() {
+        assert_eq!(error_near_the_end_of_ram(asm::ld_b_vx(0)), Chip8Error::MemoryOutOfBounds { pc: 0x202, addr: 0x1000 });
+    }
+
+    #[test]
+    fn out_of_bounds_register_store_is_an_error() {
+        assert_eq!(error_near_the_end_of_ram(asm::ld_i_vx(3)), Chip8Error::MemoryOutOfBounds { pc: 0x202, addr: 0x1000 });
+    }
+
+    #[test]
+    fn out_of_bounds_register_load_is_an_error() {
+        assert_eq!(error_near_the_end_of_ram(asm::ld_vx_i(3)), Chip8Error::MemoryOutOfBounds { pc: 0x202, addr: 0x1000 });
+    }
+
+    #[test]
+    fn accesses_that_just_fit_are_fine() {
+        // FX55 and FX65 move I past what they touch, so it's set before each
+        let mut emu = emu_with(&[
+            asm::ld_i(0xFFE),
+            asm::ld_i_vx(1),
+            asm::ld_i(0xFFE),
+            asm::ld_vx_i(1),
+            asm::ld_i(0xFFE),
+            asm::drw(0, 0, 2)
+        ]);
+
+        assert_eq!(emu.run(6), Ok(()));
+    }
+
+    #[test]
+    fn display_hash_of_a_blank_screen_is_pinned() {
+        assert_eq!(Emu::with_seed(0).display_hash(), 0x2302_2515_a197_b08d);
+    }
+
+    #[test]
+    fn display_hash_of_a_drawn_digit_is_pinned() {
+        // The digit 5 at (8, 4)
+        let mut emu = emu_with(&[
+            asm::ld_vx_byte(0, 5),
+            asm::ld_f_vx(0),
+            asm::ld_vx_byte(1, 8),
+            asm::ld_vx_byte(2, 4),
+            asm::drw(1, 2, 5),
+            asm::jp(0x20A)
+        ]);
+        assert_eq!(emu.run_until_halt(100), Ok(StopReason::Halted));
+
+        assert_eq!(emu.display_hash(), 0x33f2_0f34_bb2a_9c33);
+    }
+
+    #[test]
+    fn zero_prefixed_opcodes_reach_their_handlers() {
+        // Light the top-left pixel, then go through the 0-prefixed opcodes
+        let mut emu = emu_with(&[
+            asm::ld_i(0x21A),
+            asm::drw(0, 0, 1),
+            0x0000,
+            asm::scd(1),
+            asm::scr(),
+            asm::scu(1),
+            asm::scl(),
+            asm::cls(),
+            asm::high(),
+            asm::low(),
+            asm::call(0x218),
+            asm::jp(0x216),
+            asm::ret(),
+            0x8000
+        ]);
+        emu.run(3).unwrap();
+        assert_eq!(emu.program_counter(), 0x206);
+
+        emu.tick().unwrap();
+        assert!(emu.pixel(0, 1));
+        emu.tick().unwrap();
+        assert!(emu.pixel(4, 1));
+        emu.tick().unwrap();
+        assert!(emu.pixel(4, 0));
+        emu.tick().unwrap();
+        assert!(emu.pixel(0, 0));
+        emu.tick().unwrap();
+        assert_eq!(emu.lit_pixels().count(), 0);
+
+        emu.tick().unwrap();
+        assert!(emu.is_hires());
+        emu.tick().unwrap();
+        assert!(!emu.is_hires());
+
+        emu.tick().unwrap();
+        assert_eq!(emu.stack_pointer(), 1);
+        emu.tick().unwrap();
+        assert_eq!(emu.stack_pointer(), 0);
+        assert_eq!(emu.program_counter(), 0x216);
+    }
+
+    #[test]
+    fn machine_code_calls_are_reported_as_unsupported() {
+        let mut emu = emu_with(&[0x0123]);
+
+        assert_eq!(emu.tick(), Err(Chip8Error::MachineCodeCall(0x123)));
+    }
+
+    #[test]
+    fn unknown_zero_prefixed_opcodes_arent_run_as_machine_code() {
+        let mut emu = emu_with(&[0x00E1]);
+
+        assert_eq!(emu.tick(), Err(Chip8Error::UnknownOpcode(0x00E1)));
+    }
+
+    #[test]
+    fn font_opcodes_point_into_fonts_at_the_base_address() {
+        let mut emu = Emu::with_seed(0);
+        emu.set_font_address(0x050).unwrap();
+        emu.execute_opcode(asm::ld_vx_byte(0, 0xA)).unwrap();
+
+        emu.execute_opcode(asm::ld_f_vx(0)).unwrap();
+        assert_eq!(emu.i_register(), 0x050 + 5 * 0xA);
+        emu.execute_opcode(asm::ld_hf_vx(0)).unwrap();
+        assert_eq!(emu.i_register(), 0x050 + 80 + 10 * 0xA);
+
+        // The glyphs are really there
+        assert_eq!(emu.ram[0x050..0x050 + FONTSET_SIZE], FONTSET);
+        assert_eq!(emu.ram[0x0A0..0x0A0 + BIG_FONTSET_SIZE], BIG_FONTSET);
+        assert!(emu.ram[..0x050].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn font_address_has_to_leave_room_below_the_program() {
+        let mut emu = Emu::with_seed(0);
+
+        assert_eq!(emu.set_font_address(0x111), Err(Chip8Error::FontAddressOutOfRange(0x111)));
+        assert_eq!(emu.set_font_address(0x110), Ok(()));
+        assert_eq!(emu.font_address(), 0x110);
+    }
+
+    #[test]
+    fn lit_pixels_lists_each_lit_pixel() {
+        // Single pixels at (40, 20), (3, 7) and (63, 0)
+        let mut emu = emu_with(&[
+            asm::ld_i(0x216),
+            asm::ld_vx_byte(0, 40),
+            asm::ld_vx_byte(1, 20),
+            asm::drw(0, 1, 1),
+            asm::ld_vx_byte(0, 3),
+            asm::ld_vx_byte(1, 7),
+            asm::drw(0, 1, 1),
+            asm::ld_vx_byte(0, 63),
+            asm::ld_vx_byte(1, 0),
+            asm::drw(0, 1, 1),
+            asm::jp(0x214),
+            0x8000
+        ]);
+        emu.run(10).unwrap();
+
+        let mut lit: Vec<_> = emu.lit_pixels().collect();
+        lit.sort();
+        assert_eq!(lit, [(3, 7), (40, 20), (63, 0)]);
+    }
+
+    #[test]
+    fn ticking_random_memory_never_panics() {
+        for seed in 0..8 {
+            let mut rng = ChaCha12Rng::seed_from_u64(seed);
+            let mut emu = Emu::with_seed(seed);
+
+            // All 4096 bytes of RAM are random, fonts and stack included
+            rng.fill(&mut emu.ram[..]);
+            emu.pc = rng.gen_range(0..RAM_SIZE as u16);
+
+            for n in 0..10_000 {
+                // Each error ends the instruction it came from, so carry on
+                // from somewhere else to keep covering new opcodes
+                if emu.tick().is_err() {
+                    emu.pc = rng.gen_range(0..RAM_SIZE as u16);
+                }
+
+                // Keep the timers and FX0A moving
+                if n % 10 == 0 {
+                    emu.tick_timers();
+                    emu.keypress(rng.gen_range(0..NUM_KEYS), rng.gen());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn only_5xy0_is_se_vx_vy() {
+        let mut emu = emu_with(&[0x5121]);
+
+        assert_eq!(emu.tick(), Err(Chip8Error::UnknownOpcode(0x5121)));
+        assert_eq!(disassemble(0x5121), "UNKNOWN 0x5121");
+    }
+
+    #[test]
+    fn execute_opcode_refuses_the_long_load_of_i() {
+        let mut emu = emu_with(&[0x1234]);
+
+        assert_eq!(emu.execute_opcode(0xF000), Err(Chip8Error::MissingOperand(0xF000)));
+        assert_eq!(emu.program_counter(), START_ADDR);
+        assert_eq!(emu.i_register(), 0);
+    }
+}
@@ -0,0 +1,86 @@
+use crate::{Chip8Error, Emu};
+
+// Where two emulators running in lockstep first stopped agreeing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    pub step: usize,        // Index of the instruction that diverged, counting from 0
+    pub opcode_a: u16,      // What each emulator ran at that step
+    pub opcode_b: u16
+}
+
+// Step two emulators side by side for up to max_steps instructions and find
+// the first one after which they differ, either in the opcode they ran or in
+// their registers, stack, timers, or screen. Set them up beforehand with
+// different ROMs, or the same ROM under different quirks, to see where a patch
+// or a compatibility setting starts to matter. RAM isn't compared, since two
+// ROMs start out with different RAM; a difference in what gets written there
+// shows up once it's read back.
+//
+// Returns None if they still agree after max_steps. If only one of them hits
+// an error, or they hit different errors, that counts as diverging; if both
+// fail the same way the error is returned
+pub fn find_divergence(a: &mut Emu, b: &mut Emu, max_steps: usize) -> Result<Option<Divergence>, Chip8Error> {
+    for step in 0..max_steps {
+        let result_a = a.tick();
+        let result_b = b.tick();
+
+        let divergence = Divergence {
+            step,
+            opcode_a: a.last_opcode(),
+            opcode_b: b.last_opcode()
+        };
+
+        match (result_a, result_b) {
+            (Ok(()), Ok(())) => (),
+            (Err(e_a), Err(e_b)) if e_a == e_b => return Err(e_a),
+            _ => return Ok(Some(divergence))
+        }
+
+        if divergence.opcode_a != divergence.opcode_b || a.machine_hash() != b.machine_hash() {
+            return Ok(Some(divergence));
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{asm, Quirks};
+
+    fn emu_with(program: &[u16]) -> Emu {
+        let mut emu = Emu::with_seed(0);
+        emu.load(&asm::to_bytes(program)).unwrap();
+        emu
+    }
+
+    #[test]
+    fn finds_the_first_instruction_that_differs() {
+        let mut a = emu_with(&[asm::ld_vx_byte(0, 1), asm::ld_vx_byte(1, 2), asm::ld_vx_byte(2, 3)]);
+        let mut b = emu_with(&[asm::ld_vx_byte(0, 1), asm::ld_vx_byte(1, 2), asm::ld_vx_byte(2, 4)]);
+
+        assert_eq!(find_divergence(&mut a, &mut b, 10), Ok(Some(Divergence {
+            step: 2,
+            opcode_a: 0x6203,
+            opcode_b: 0x6204
+        })));
+    }
+
+    #[test]
+    fn finds_a_state_difference_under_the_same_opcode() {
+        // Only 8106's shift source differs between the two
+        let mut a = emu_with(&[asm::ld_vx_byte(0, 4), asm::ld_vx_byte(1, 8), asm::shr(1, 0)]);
+        let mut b = emu_with(&[asm::ld_vx_byte(0, 4), asm::ld_vx_byte(1, 8), asm::shr(1, 0)]);
+        b.set_quirks(Quirks { shift_uses_vy: true, ..Quirks::default() });
+
+        assert_eq!(find_divergence(&mut a, &mut b, 10).unwrap().map(|d| d.step), Some(2));
+    }
+
+    #[test]
+    fn agreeing_emulators_dont_diverge() {
+        let program = [asm::ld_vx_byte(0, 1), asm::jp(0x202)];
+
+        assert_eq!(find_divergence(&mut emu_with(&program), &mut emu_with(&program), 50), Ok(None));
+    }
+}
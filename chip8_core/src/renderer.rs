@@ -0,0 +1,62 @@
+// Something that can put the emulator's display in front of a player, such as
+// a window, a canvas on a web page, or a terminal. Frontends implement this so
+// that code driving the emulator doesn't need to know how frames are shown
+pub trait Renderer {
+    // Show one frame. screen holds width * height pixels, row by row, with
+    // true for a lit pixel. The size changes with the resolution mode
+    fn draw(&mut self, screen: &[bool], width: usize, height: usize);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{asm, Emu};
+
+    // Shows nothing, for driving the emulator where there's no screen
+    struct NullRenderer;
+
+    impl Renderer for NullRenderer {
+        fn draw(&mut self, _screen: &[bool], _width: usize, _height: usize) {}
+    }
+
+    // Keeps the size and lit pixel count of each frame it's given
+    #[derive(Default)]
+    struct RecordingRenderer {
+        frames: Vec<(usize, usize, usize)>
+    }
+
+    impl Renderer for RecordingRenderer {
+        fn draw(&mut self, screen: &[bool], width: usize, height: usize) {
+            assert_eq!(screen.len(), width * height);
+            self.frames.push((width, height, screen.iter().filter(|pixel| **pixel).count()));
+        }
+    }
+
+    #[test]
+    fn null_renderer_can_drive_the_emulator() {
+        let mut emu = Emu::with_seed(0);
+        emu.load(&asm::to_bytes(&[asm::drw(0, 0, 5), asm::jp(0x202)])).unwrap();
+
+        for _ in 0..10 {
+            emu.run(10).unwrap();
+            emu.tick_timers();
+            emu.render(&mut NullRenderer);
+        }
+    }
+
+    #[test]
+    fn render_hands_over_the_active_display() {
+        // Draw the digit 0 sprite, which lights 14 pixels, then switch to hires
+        let mut emu = Emu::with_seed(0);
+        emu.load(&asm::to_bytes(&[asm::drw(0, 0, 5), asm::high()])).unwrap();
+        let mut renderer = RecordingRenderer::default();
+
+        emu.tick().unwrap();
+        emu.render(&mut renderer);
+        emu.tick().unwrap();
+        emu.render(&mut renderer);
+
+        assert_eq!(renderer.frames[0], (64, 32, 14));
+        assert_eq!((renderer.frames[1].0, renderer.frames[1].1), (128, 64));
+    }
+}
@@ -0,0 +1,34 @@
+// How many instructions of each kind a program has run, grouped by the
+// opcode's first hex digit (0x1 for JP, 0xD for DRW, and so on), for seeing
+// which instructions a ROM spends its time on
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InstructionStats {
+    counts: [u64; 16]                               // Instructions run, indexed by first nibble
+}
+
+impl InstructionStats {
+    // Instructions run whose opcode starts with the given hex digit. Only the
+    // low 4 bits of the digit are used
+    pub fn count(&self, first_nibble: u8) -> u64 {
+        self.counts[(first_nibble & 0xF) as usize]
+    }
+
+    // Instructions run altogether
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    // The count for each first nibble, from 0x0 to 0xF
+    pub fn counts(&self) -> &[u64; 16] {
+        &self.counts
+    }
+
+    pub(crate) fn record(&mut self, op: u16) {
+        let group = (op >> 12) as usize;
+        self.counts[group] = self.counts[group].saturating_add(1);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.counts = [0; 16];
+    }
+}
@@ -0,0 +1,101 @@
+use js_sys::{Promise, Uint8Array};
+use wasm_bindgen::prelude::*;
+
+use crate::Emu;
+
+#[wasm_bindgen(inline_js = "
+export async function fetch_rom(url, load) {
+    const response = await fetch(url);
+    if (!response.ok) {
+        throw new Error(`Couldn't fetch ROM from ${url}: ${response.status} ${response.statusText}`);
+    }
+    return load(new Uint8Array(await response.arrayBuffer()));
+}
+")]
+extern "C" {
+    // Fetch url and resolve to what load returns when called with its bytes.
+    // Rejects if the request fails, the server answers with an error status,
+    // or load throws
+    fn fetch_rom(url: &str, load: &JsValue) -> Promise;
+}
+
+// The emulator as seen from JavaScript, with just enough of its API to run
+// a game in a web page: load a ROM, then each frame call tick a few times
+// and tick_timers once, pass on key presses, and draw display()
+#[wasm_bindgen]
+pub struct WasmEmu {
+    emu: Emu
+}
+
+#[wasm_bindgen]
+impl WasmEmu {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self { emu: Emu::new() }
+    }
+
+    // Fetch a ROM and resolve to a new emulator with it loaded, so a page can
+    // start a game by URL rather than through a file picker:
+    //
+    //     const emu = await WasmEmu.fromUrl("roms/pong.ch8");
+    //
+    // Rejects with the error's message if the fetch fails, the server answers
+    // with an error status, or the ROM is too large for memory. Load
+    // warnings aren't reported; use fetch and load for those
+    #[wasm_bindgen(js_name = fromUrl)]
+    pub fn from_url(url: &str) -> Promise {
+        let load = Closure::once_into_js(|rom: Uint8Array| -> Result<JsValue, JsError> {
+            let mut emu = WasmEmu::new();
+            emu.load(&rom.to_vec())?;
+
+            Ok(emu.into())
+        });
+
+        fetch_rom(url, &load)
+    }
+
+    // Errors reach JavaScript as exceptions with the error's message. A ROM
+    // that loads with a warning returns the warning's message, otherwise
+    // undefined
+    pub fn load(&mut self, data: &[u8]) -> Result<Option<String>, JsError> {
+        self.emu.load(data)
+            .map(|warning| warning.map(|w| w.to_string()))
+            .map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    pub fn tick(&mut self) -> Result<(), JsError> {
+        self.emu.tick().map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    pub fn tick_timers(&mut self) {
+        self.emu.tick_timers();
+    }
+
+    pub fn keypress(&mut self, key: usize, pressed: bool) {
+        self.emu.keypress(key, pressed);
+    }
+
+    // One byte per pixel, row by row: 1 if lit, 0 if not. The size follows
+    // display_width and display_height, which change with the resolution mode
+    pub fn display(&self) -> Vec<u8> {
+        self.emu.get_display().iter().map(|pixel| *pixel as u8).collect()
+    }
+
+    pub fn display_width(&self) -> usize {
+        self.emu.display_dimensions().0
+    }
+
+    pub fn display_height(&self) -> usize {
+        self.emu.display_dimensions().1
+    }
+
+    pub fn is_beeping(&self) -> bool {
+        self.emu.is_beeping()
+    }
+}
+
+impl Default for WasmEmu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
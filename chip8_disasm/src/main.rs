@@ -0,0 +1,23 @@
+use chip8_core::disassemble_listing;
+
+use std::env;
+use std::fs;
+use std::process;
+
+// Print every instruction in a ROM alongside its address, as it would be laid
+// out in memory once loaded
+fn main() {
+    let args: Vec<_> = env::args().collect();
+    if args.len() != 2 {
+        println!("Usage: cargo run --bin disasm path/to/rom");
+        process::exit(1);
+    }
+
+    let filepath = &args[1];
+    let rom = fs::read(filepath).unwrap_or_else(|e| {
+        println!("Could not read {}: {}", filepath, e);
+        process::exit(1);
+    });
+
+    print!("{}", disassemble_listing(&rom));
+}
@@ -0,0 +1,21 @@
+use sdl2::audio::AudioCallback;
+use chip8_core::{generate_audio, Waveform};
+
+pub const BEEP_FREQ: f32 = 440.0;
+pub const BEEP_VOLUME: f32 = 0.25;
+
+// Tone generator fed to the SDL audio device for the beep
+pub struct Beeper {
+    pub waveform: Waveform,
+    pub phase_inc: f32,
+    pub phase: f32,
+    pub volume: f32
+}
+
+impl AudioCallback for Beeper {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        generate_audio(self.waveform, out, &mut self.phase, self.phase_inc, self.volume);
+    }
+}
@@ -0,0 +1,68 @@
+use chip8_core::{Renderer, HIRES_SCREEN_HEIGHT, HIRES_SCREEN_WIDTH};
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture, TextureCreator};
+use sdl2::video::{Window, WindowContext};
+
+// Bytes per pixel in the texture
+const BYTES_PER_PIXEL: usize = 4;
+
+// Draws the display into an SDL window in two colors. Each frame's pixels are
+// written into a texture, which the GPU then scales up to the window in a
+// single copy
+pub struct SdlRenderer<'a> {
+    canvas: Canvas<Window>,
+    texture: Texture<'a>,                           // Big enough for either resolution; only the top left is used in low resolution
+    pixels: Vec<u8>,                                // RGBA staging buffer for the texture
+    fg: Color,
+    bg: Color
+}
+
+impl<'a> SdlRenderer<'a> {
+    pub fn new(canvas: Canvas<Window>, texture_creator: &'a TextureCreator<WindowContext>, fg: Color, bg: Color) -> Self {
+        let texture = texture_creator
+            .create_texture_streaming(PixelFormatEnum::RGBA32, HIRES_SCREEN_WIDTH as u32, HIRES_SCREEN_HEIGHT as u32)
+            .unwrap();
+
+        Self {
+            canvas,
+            texture,
+            pixels: vec![0; HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT * BYTES_PER_PIXEL],
+            fg,
+            bg
+        }
+    }
+}
+
+impl Renderer for SdlRenderer<'_> {
+    fn draw(&mut self, screen: &[bool], width: usize, height: usize) {
+        // RGBA32 is laid out as R, G, B, A in memory whatever the byte order
+        for (pixel, rgba) in screen.iter().zip(self.pixels.chunks_exact_mut(BYTES_PER_PIXEL)) {
+            let color = if *pixel { self.fg } else { self.bg };
+            rgba.copy_from_slice(&[color.r, color.g, color.b, 0xFF]);
+        }
+
+        let src = Rect::new(0, 0, width as u32, height as u32);
+        self.texture.update(src, &self.pixels[..width * height * BYTES_PER_PIXEL], width * BYTES_PER_PIXEL).unwrap();
+
+        let (width, height) = (width as u32, height as u32);
+
+        // Stretch the display as large as the window allows without changing
+        // its shape, and center it, leaving bars of background on the sides
+        // or top and bottom
+        let (window_width, window_height) = self.canvas.output_size().unwrap();
+        let (area_width, area_height) = if window_width * height > window_height * width {
+            (window_height * width / height, window_height)
+        } else {
+            (window_width, window_width * height / width)
+        };
+        let area_left = (window_width - area_width) / 2;
+        let area_top = (window_height - area_height) / 2;
+        let dst = Rect::new(area_left as i32, area_top as i32, area_width, area_height);
+
+        self.canvas.set_draw_color(self.bg);
+        self.canvas.clear();
+        self.canvas.copy(&self.texture, src, dst).unwrap();
+        self.canvas.present();
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+
+// A recorded sequence of keypad changes, keyed by the frame they happen on.
+//
+// Each non-empty line of a script is a frame number followed by one or more
+// `key=state` pairs, where key is a hex digit 0-F and state is 1 (pressed) or
+// 0 (released). Anything after a '#' is a comment:
+//
+//     # press 5 on frame 120 and let go ten frames later
+//     120 5=1
+//     130 5=0
+pub struct InputScript {
+    events: BTreeMap<u64, Vec<(usize, bool)>>
+}
+
+impl InputScript {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut events: BTreeMap<u64, Vec<(usize, bool)>> = BTreeMap::new();
+
+        for (i, raw_line) in text.lines().enumerate() {
+            let line_no = i + 1;
+            let line = match raw_line.find('#') {
+                Some(idx) => &raw_line[..idx],
+                None => raw_line
+            }.trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut fields = line.split_whitespace();
+
+            let frame_field = fields.next().unwrap();
+            let frame: u64 = frame_field.parse()
+                .map_err(|_| format!("line {}: invalid frame number '{}'", line_no, frame_field))?;
+
+            let mut changes = Vec::new();
+            for field in fields {
+                let (key_str, state_str) = field.split_once('=')
+                    .ok_or_else(|| format!("line {}: expected key=state, found '{}'", line_no, field))?;
+
+                let key = usize::from_str_radix(key_str, 16)
+                    .ok()
+                    .filter(|k| *k < 16)
+                    .ok_or_else(|| format!("line {}: invalid key '{}', expected 0-F", line_no, key_str))?;
+
+                let pressed = match state_str {
+                    "1" => true,
+                    "0" => false,
+                    _ => return Err(format!("line {}: invalid state '{}', expected 0 or 1", line_no, state_str))
+                };
+
+                changes.push((key, pressed));
+            }
+
+            if changes.is_empty() {
+                return Err(format!("line {}: no key states given for frame {}", line_no, frame));
+            }
+
+            events.entry(frame).or_default().extend(changes);
+        }
+
+        Ok(Self { events })
+    }
+
+    // Key changes that should be applied at the start of the given frame
+    pub fn events_for(&self, frame: u64) -> &[(usize, bool)] {
+        self.events.get(&frame).map(|e| e.as_slice()).unwrap_or(&[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_core::{asm, Emu};
+
+    #[test]
+    fn parses_frames_keys_and_comments() {
+        let script = InputScript::parse("# header\n\n120 5=1 a=1  # two keys\n130 5=0\n120 F=0\n").unwrap();
+
+        assert_eq!(script.events_for(120), &[(0x5, true), (0xA, true), (0xF, false)]);
+        assert_eq!(script.events_for(130), &[(0x5, false)]);
+        assert!(script.events_for(125).is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        for bad in ["x 5=1", "10", "10 5", "10 G=1", "10 10=1", "10 5=2"] {
+            let err = InputScript::parse(&format!("# ok\n{}\n", bad)).err();
+            assert!(err.is_some_and(|e| e.starts_with("line 2:")), "accepted '{}'", bad);
+        }
+    }
+
+    #[test]
+    fn script_drives_a_run_to_a_known_screen() {
+        // Wait for a key, then draw its digit in the top left corner
+        let rom = asm::to_bytes(&[
+            asm::ld_vx_k(0),
+            asm::ld_f_vx(0),
+            asm::drw(1, 1, 5),
+            asm::jp(0x206)
+        ]);
+        let script = InputScript::parse("2 7=1\n3 7=0\n").unwrap();
+
+        let mut emu = Emu::with_seed(0);
+        emu.load(&rom).unwrap();
+
+        for frame in 0..6 {
+            for &(key, pressed) in script.events_for(frame) {
+                emu.keypress(key, pressed);
+            }
+            emu.run(6).unwrap();
+            emu.tick_timers();
+        }
+
+        // The same digit drawn without going through the keypad
+        let mut expected = Emu::with_seed(0);
+        expected.load(&asm::to_bytes(&[asm::ld_vx_byte(0, 7), asm::ld_f_vx(0), asm::drw(1, 1, 5)])).unwrap();
+        expected.run(3).unwrap();
+
+        assert_eq!(emu.registers()[0], 7);
+        assert_eq!(emu.display_hash(), expected.display_hash());
+    }
+}
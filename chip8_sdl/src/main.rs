@@ -1,79 +1,390 @@
 use sdl2::event::Event;
 use chip8_core::*;
-use sdl2::render::Canvas;
 use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use sdl2::video::Window;
 use sdl2::keyboard::Keycode;
+use sdl2::audio::AudioSpecDesired;
+use sdl2::controller::{Button, GameController};
 
 use std::env;
-use std::fs::File;
-use std::io::Read;
+use std::fs;
+use std::io::{self, Read};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
 
+mod audio;
+mod display;
+mod input_script;
+mod stream;
+mod trace_log;
+use audio::Beeper;
+use display::SdlRenderer;
+use input_script::InputScript;
+use stream::FrameStream;
+use trace_log::TraceLog;
+
+// Window pixels per CHIP-8 pixel when the window opens
 const SCALE: u32 = 15;
-const WINDOW_WIDTH: u32 = (chip8_core::SCREEN_WIDTH as u32) * SCALE;
-const WINDOW_HEIGHT: u32 = (chip8_core::SCREEN_HEIGHT as u32) * SCALE;
+
+// Largest --scale, which opens a 4096x2048 window. Anything much bigger is
+// past what SDL and most displays can show, and far enough out overflows
+// the window size
+const MAX_SCALE: u32 = 64;
 
 const TICKS_PER_FRAME: u32 = 6;
 
-fn k_to_btn(k: Keycode) -> Option<usize> {
+// The timers count down at 60 Hz, and a frame's worth of instructions runs
+// alongside each count
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+// After a stall (e.g. the window being dragged) run at most this many frames
+// at once to catch up, rather than fast-forwarding through everything missed
+const MAX_CATCHUP_FRAMES: u32 = 4;
+const COLLISION_DELAY_MS: u64 = 250;
+
+// How many frames run in the time of one while Tab is held
+const TURBO_FACTOR: u32 = 10;
+
+// Sleep this long each frame once the emulator is paused or has been idle
+// for a while. Short enough that input still feels immediate
+const IDLE_SLEEP_MS: u64 = 4;
+const IDLE_THRESHOLD: u32 = 30;
+
+const USAGE: &str = "Usage: cargo run path/to/game [options]
+
+Use - as the path to read the game from stdin.
+
+Options:
+    --ipf <n>                 Instructions to run per frame (default 6)
+    --scale <n>               Starting window size in screen pixels per CHIP-8 pixel, 1 to 64 (default 15)
+    --polls-per-frame <n>     Times to read input each frame (default 1)
+    --fg <RRGGBB>             Foreground color as a hex code (default FFFFFF)
+    --bg <RRGGBB>             Background color as a hex code (default 000000)
+    --wave <shape>            Beep waveform: square, sine, triangle, or sawtooth
+    --collision-delay <ms>    How long F2 collision slow motion holds a frame
+    --idle-threshold <n>      Idle frames before sleeping to save CPU; 0 disables (default 30)
+    --inputs <path>           Play back keypad input from a script
+    --stream <addr>           Serve the display over a WebSocket, e.g. 127.0.0.1:9000
+    --trace <path>            Log the registers after every instruction to a CSV file
+    --show-speed              Print instructions and frames run per second";
+
+// Command-line options for the frontend
+struct Options {
+    rom_path: String,
+    ticks_per_frame: u32,
+    polls_per_frame: u32,
+    scale: u32,
+    inputs_path: Option<String>,
+    collision_delay_ms: u64,
+    waveform: Waveform,
+    fg: Color,
+    bg: Color,
+    idle_threshold: u32,
+    stream_addr: Option<String>,
+    trace_path: Option<String>,
+    show_speed: bool
+}
+
+// Parse a color written as RRGGBB, optionally with a leading '#'
+fn parse_color(hex: &str) -> Result<Color, String> {
+    let digits = hex.strip_prefix('#').unwrap_or(hex);
+
+    if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Invalid color '{}', expected a hex code like FFB000", hex));
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).unwrap();
+
+    Ok(Color::RGB(channel(0), channel(2), channel(4)))
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut rom_path = None;
+    let mut ticks_per_frame = TICKS_PER_FRAME;
+    let mut polls_per_frame = 1;
+    let mut scale = SCALE;
+    let mut inputs_path = None;
+    let mut collision_delay_ms = COLLISION_DELAY_MS;
+    let mut waveform = Waveform::default();
+    let mut fg = Color::RGB(255,255,255);
+    let mut bg = Color::RGB(0,0,0);
+    let mut idle_threshold = IDLE_THRESHOLD;
+    let mut stream_addr = None;
+    let mut trace_path = None;
+    let mut show_speed = false;
+
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--ipf" => {
+                let value = iter.next().ok_or("--ipf requires a number")?;
+                ticks_per_frame = match value.parse() {
+                    Ok(n) if n > 0 => n,
+                    _ => return Err(format!("--ipf must be a positive integer, got '{}'", value))
+                };
+            },
+            "--polls-per-frame" => {
+                let value = iter.next().ok_or("--polls-per-frame requires a number")?;
+                polls_per_frame = match value.parse() {
+                    Ok(n) if n > 0 => n,
+                    _ => return Err(format!("--polls-per-frame must be a positive integer, got '{}'", value))
+                };
+            },
+            "--scale" => {
+                let value = iter.next().ok_or("--scale requires a number")?;
+                scale = match value.parse() {
+                    Ok(n) if (1..=MAX_SCALE).contains(&n) => n,
+                    _ => return Err(format!("--scale must be an integer from 1 to {}, got '{}'", MAX_SCALE, value))
+                };
+            },
+            "--fg" => {
+                let value = iter.next().ok_or("--fg requires a color")?;
+                fg = parse_color(value)?;
+            },
+            "--bg" => {
+                let value = iter.next().ok_or("--bg requires a color")?;
+                bg = parse_color(value)?;
+            },
+            "--idle-threshold" => {
+                let value = iter.next().ok_or("--idle-threshold requires a number of frames")?;
+                idle_threshold = value.parse()
+                    .map_err(|_| format!("--idle-threshold must be a number of frames, got '{}'", value))?;
+            },
+            "--inputs" => {
+                let path = iter.next().ok_or("--inputs requires a path")?;
+                inputs_path = Some(path.clone());
+            },
+            "--show-speed" => {
+                show_speed = true;
+            },
+            "--stream" => {
+                let addr = iter.next().ok_or("--stream requires an address")?;
+                stream_addr = Some(addr.clone());
+            },
+            "--trace" => {
+                let path = iter.next().ok_or("--trace requires a path")?;
+                trace_path = Some(path.clone());
+            },
+            "--collision-delay" => {
+                let value = iter.next().ok_or("--collision-delay requires a number of milliseconds")?;
+                collision_delay_ms = value.parse()
+                    .map_err(|_| format!("--collision-delay must be a number of milliseconds, got '{}'", value))?;
+            },
+            "--wave" => {
+                let value = iter.next().ok_or("--wave requires a waveform")?;
+                waveform = value.parse()?;
+            },
+            _ if arg.starts_with("--") => {
+                return Err(format!("Unknown option '{}'", arg));
+            },
+            _ => {
+                if rom_path.is_some() {
+                    return Err(format!("Unexpected argument '{}'", arg));
+                }
+                rom_path = Some(arg.clone());
+            }
+        }
+    }
+
+    let rom_path = rom_path.ok_or("No ROM given")?;
+
+    Ok(Options { rom_path, ticks_per_frame, polls_per_frame, scale, inputs_path, collision_delay_ms, waveform, fg, bg, idle_threshold, stream_addr, trace_path, show_speed })
+}
+
+// The keypad is laid out on the left of a QWERTY keyboard:
+//     1 2 3 C        1 2 3 4
+//     4 5 6 D   <-   Q W E R
+//     7 8 9 E        A S D F
+//     A 0 B F        Z X C V
+fn k_to_btn(k: Keycode) -> Option<Key> {
     match k {
-        Keycode::Num1 => Some(0x1),
-        Keycode::Num2 => Some(0x2),
-        Keycode::Num3 => Some(0x3),
-        Keycode::Num4 => Some(0xC),
-        Keycode::Q => Some(0x4),
-        Keycode::W => Some(0x5),
-        Keycode::E => Some(0x6),
-        Keycode::R => Some(0xD),
-        Keycode::A => Some(0x7),
-        Keycode::S => Some(0x8),
-        Keycode::D => Some(0x9),
-        Keycode::F => Some(0xE),
-        Keycode::Z => Some(0xA),
-        Keycode::X => Some(0x0),
-        Keycode::C => Some(0xB),
-        Keycode::V => Some(0xF),
+        Keycode::Num1 => Some(Key::Num1),
+        Keycode::Num2 => Some(Key::Num2),
+        Keycode::Num3 => Some(Key::Num3),
+        Keycode::Num4 => Some(Key::C),
+        Keycode::Q => Some(Key::Num4),
+        Keycode::W => Some(Key::Num5),
+        Keycode::E => Some(Key::Num6),
+        Keycode::R => Some(Key::D),
+        Keycode::A => Some(Key::Num7),
+        Keycode::S => Some(Key::Num8),
+        Keycode::D => Some(Key::Num9),
+        Keycode::F => Some(Key::E),
+        Keycode::Z => Some(Key::A),
+        Keycode::X => Some(Key::Num0),
+        Keycode::C => Some(Key::B),
+        Keycode::V => Some(Key::F),
         _ => None,
     }
 
 }
 
-fn draw_screen(emu: &chip8_core::Emu, canvas: &mut Canvas<Window>){
-    canvas.set_draw_color(Color::RGB(0,0,0));
-    canvas.clear();
+// Controllers get the keys most games use for movement on the d-pad, and the
+// usual action key and a few others on the face buttons
+fn button_to_btn(button: Button) -> Option<Key> {
+    match button {
+        Button::DPadUp => Some(Key::Num2),
+        Button::DPadDown => Some(Key::Num8),
+        Button::DPadLeft => Some(Key::Num4),
+        Button::DPadRight => Some(Key::Num6),
+        Button::A => Some(Key::Num5),
+        Button::B => Some(Key::Num0),
+        Button::X => Some(Key::Num7),
+        Button::Y => Some(Key::Num9),
+        Button::Start => Some(Key::F),
+        Button::Back => Some(Key::E),
+        _ => None
+    }
+}
+
+// Read a whole ROM from a file, or from stdin if the path is "-"
+fn read_rom(path: &str) -> io::Result<Vec<u8>> {
+    if path == "-" {
+        let mut buffer = Vec::new();
+        io::stdin().read_to_end(&mut buffer)?;
+        Ok(buffer)
+    } else {
+        fs::read(path)
+    }
+}
+
+// How to refer to the ROM at a path in messages
+fn rom_name(path: &str) -> &str {
+    if path == "-" { "stdin" } else { path }
+}
+
+// Write the emulator's state to disk so it can be restored later with load_state
+fn save_state(emu: &chip8_core::Emu, path: &str) {
+    let result = bincode::serialize(&emu.save_state())
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| fs::write(path, bytes).map_err(|e| e.to_string()));
 
-    let screen_buf = emu.get_display();
+    match result {
+        Ok(()) => println!("Saved state to {}", path),
+        Err(e) => println!("Could not save state to {}: {}", path, e)
+    }
+}
 
-    canvas.set_draw_color(Color::RGB(255,255,255));
-    for (i, pixel) in screen_buf.iter().enumerate() {
-        if *pixel {
-            let x = (i % SCREEN_WIDTH) as u32;
-            let y = (i / SCREEN_WIDTH) as u32;
-            
-            let rect = Rect::new((x * SCALE) as i32, (y * SCALE) as i32, SCALE, SCALE);
-            canvas.fill_rect(rect).unwrap();
+fn load_state(emu: &mut chip8_core::Emu, path: &str) {
+    let result = fs::read(path)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| bincode::deserialize::<EmuState>(&bytes).map_err(|e| e.to_string()))
+        .and_then(|state| emu.load_state(&state).map_err(|e| e.to_string()));
+
+    match result {
+        Ok(()) => println!("Loaded state from {}", path),
+        Err(e) => println!("Could not load state from {}: {}", path, e)
+    }
+}
+
+// Run one instruction, reporting why emulation has to stop if it fails, and
+// log it if --trace is on. Returns whether emulation can carry on
+fn tick_or_report(emu: &mut chip8_core::Emu, trace: &mut Option<TraceLog>) -> bool {
+    let addr = emu.program_counter();
+
+    match emu.tick() {
+        Ok(()) => {
+            if let Some(log) = trace {
+                if let Err(e) = log.record(addr, emu) {
+                    println!("Could not write the trace, no longer tracing: {}", e);
+                    *trace = None;
+                }
+            }
+            true
+        },
+        Err(Chip8Error::UnknownOpcode(op)) => {
+            println!("Unknown opcode {:#06x} at {:#06x}, stopping", op, emu.program_counter());
+            false
+        },
+        Err(e) => {
+            println!("Emulation stopped: {}", e);
+            false
         }
     }
+}
 
-    canvas.present();
+// Print where a paused program is and what it will run next
+fn print_step(emu: &chip8_core::Emu) {
+    match emu.peek_opcode() {
+        Some(op) => println!("PC {:#06x}  next {:04X}  {}", emu.program_counter(), op, disassemble(op)),
+        None => println!("PC {:#06x}  next (past the end of RAM)", emu.program_counter())
+    }
 }
 
 fn main() {
     let args: Vec<_> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: cargo run path/to/game");
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(msg) => {
+            println!("{}", msg);
+            println!("{}", USAGE);
+            return;
+        }
+    };
+
+    let filepath = &options.rom_path;
+    // let filepath = "/home/linkachu/rustProjects/chip8_emu/c8games/TETRIS";
+
+    // Load the input script up front so a bad script is reported before the window opens
+    let input_script = options.inputs_path.as_ref().map(|path| {
+        let text = fs::read_to_string(path).unwrap_or_else(|e| {
+            println!("Could not read input script {}: {}", path, e);
+            process::exit(1);
+        });
+
+        InputScript::parse(&text).unwrap_or_else(|e| {
+            println!("Invalid input script {}: {}", path, e);
+            process::exit(1);
+        })
+    });
+
+    let mut frame_stream = options.stream_addr.as_ref().map(|addr| {
+        let stream = FrameStream::bind(addr).unwrap_or_else(|e| {
+            println!("Could not listen on {}: {}", addr, e);
+            process::exit(1);
+        });
+
+        println!("Streaming the display on ws://{}", addr);
+        stream
+    });
+
+    let mut trace = options.trace_path.as_ref().map(|path| {
+        TraceLog::create(path).unwrap_or_else(|e| {
+            println!("Could not create trace file {}: {}", path, e);
+            process::exit(1);
+        })
+    });
+
+    let mut chip8 = chip8_core::Emu::new();
+
+    let buffer = match read_rom(filepath) {
+        Ok(buffer) => buffer,
+        Err(e) => {
+            println!("Could not read {}: {}", rom_name(filepath), e);
+            return;
+        }
+    };
+
+    if buffer.is_empty() {
+        println!("Could not load {}: the ROM is empty", rom_name(filepath));
         return;
     }
 
-    let filepath = &args[1];
-    // let filepath = "/home/linkachu/rustProjects/chip8_emu/c8games/TETRIS";
+    match chip8.load(&buffer) {
+        Ok(Some(warning)) => println!("Warning: {}", warning),
+        Ok(None) => (),
+        Err(e) => {
+            println!("Could not load {}: {}", rom_name(filepath), e);
+            return;
+        }
+    }
 
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
     let window = video_subsystem
-        .window("Chip-8 Emulator", WINDOW_WIDTH, WINDOW_HEIGHT)
+        .window("Chip-8 Emulator", SCREEN_WIDTH as u32 * options.scale, SCREEN_HEIGHT as u32 * options.scale)
         .position_centered()
+        .resizable()
         .opengl()
         .build()
         .unwrap();
@@ -82,41 +393,324 @@ fn main() {
     canvas.clear();
     canvas.present();
 
+    let texture_creator = canvas.texture_creator();
+    let mut renderer = SdlRenderer::new(canvas, &texture_creator, options.fg, options.bg);
+
+    // Open the audio device once up front; it's paused and resumed each frame to beep
+    let audio_subsystem = sdl_context.audio().unwrap();
+    let desired_spec = AudioSpecDesired {
+        freq: Some(44100),
+        channels: Some(1),
+        samples: None
+    };
+    let audio_device = audio_subsystem.open_playback(None, &desired_spec, |spec| {
+        Beeper {
+            waveform: options.waveform,
+            phase_inc: audio::BEEP_FREQ / spec.freq as f32,
+            phase: 0.0,
+            volume: audio::BEEP_VOLUME
+        }
+    }).unwrap();
+
+    // SDL reports controllers that are already plugged in as being added, so
+    // the first one is picked up by the event loop, like any connected later
+    let controller_subsystem = sdl_context.game_controller().unwrap();
+    let mut controller: Option<GameController> = None;
+
     let mut event_pump = sdl_context.event_pump().unwrap();
 
-    let mut chip8 = chip8_core::Emu::new();
+    // F5 saves and F9 restores a snapshot kept next to the ROM. A ROM from
+    // stdin has nowhere to keep one
+    let state_path = (filepath != "-").then(|| format!("{}.state", filepath));
+
+    // Counts emulated frames since the game started, or was last reset with
+    // Backspace, for timing scripted input
+    let mut frame: u64 = 0;
+
+    // When on (toggled with F2), hold on any frame where sprites collided
+    let mut slow_collisions = false;
 
-    let mut rom = File::open(filepath).expect("File not found");
-    let mut buffer: Vec<u8> = vec![];
+    // Consecutive frames where the program was spinning in place
+    let mut idle_frames: u32 = 0;
 
-    rom.read_to_end(&mut buffer).unwrap();
-    chip8.load(&buffer);
+    // While paused (toggled with Space) the program and its timers are held,
+    // and the Right arrow runs one instruction at a time
+    let mut paused = false;
+
+    // Frames are only redrawn when the display changes, or when the window
+    // needs repainting after being resized or uncovered
+    let mut window_changed = false;
+
+    // While Tab is held, whole frames (instructions and timers alike) run
+    // TURBO_FACTOR times as often, so the game plays as normal, only faster
+    let mut turbo = false;
+
+    // Emulated frames run at 60 Hz by the clock, however often the screen
+    // refreshes. Time that hasn't added up to a whole frame yet carries over
+    let mut last_time = Instant::now();
+    let mut lag = Duration::ZERO;
+
+    // For --show-speed
+    let mut speed_start = Instant::now();
+    let mut speed_ticks: u32 = 0;
+    let mut speed_frames: u32 = 0;
 
     'gameloop: loop {
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit {..} => {
-                    break 'gameloop;
-                },
-                Event::KeyDown {keycode: Some(k), .. } => {
-                    if let Some(btn) = k_to_btn(k) {
-                        chip8.keypress(btn, true);
+        let now = Instant::now();
+        lag = (lag + (now - last_time)).min(FRAME_DURATION * MAX_CATCHUP_FRAMES);
+        last_time = now;
+
+        let frames_due = (lag.as_nanos() / FRAME_DURATION.as_nanos()) as u32;
+        lag -= FRAME_DURATION * frames_due;
+        let frames_due = if turbo { frames_due * TURBO_FACTOR } else { frames_due };
+
+        let mut collided = false;
+
+        // When no frame is due yet, go through once without running anything
+        // so input is still read
+        for pass in 0..frames_due.max(1) {
+            let run_frame = pass < frames_due;
+
+            if run_frame {
+                if let Some(script) = &input_script {
+                    for &(btn, pressed) in script.events_for(frame) {
+                        chip8.keypress(btn, pressed);
+                    }
+                }
+            }
+
+            if let Some(stream) = &mut frame_stream {
+                for (btn, pressed) in stream.poll_keys() {
+                    chip8.keypress(btn, pressed);
+                }
+            }
+
+            // Input is read between batches of instructions rather than once a
+            // frame, so fast games see key changes sooner
+            for batch in 0..options.polls_per_frame {
+                for event in event_pump.poll_iter() {
+                    match event {
+                        Event::Quit {..} => {
+                            break 'gameloop;
+                        },
+                        Event::Window {..} => {
+                            window_changed = true;
+                        },
+                        Event::KeyDown {keycode: Some(Keycode::Tab), repeat: false, .. } => {
+                            turbo = true;
+                        },
+                        Event::KeyUp {keycode: Some(Keycode::Tab), .. } => {
+                            turbo = false;
+                        },
+                        Event::KeyDown {keycode: Some(Keycode::F2), repeat: false, .. } => {
+                            slow_collisions = !slow_collisions;
+                            println!("Collision slow motion {}", if slow_collisions { "on" } else { "off" });
+                        },
+                        Event::KeyDown {keycode: Some(Keycode::F5), repeat: false, .. } => {
+                            match &state_path {
+                                Some(path) => save_state(&chip8, path),
+                                None => println!("Save states aren't available for a ROM read from stdin")
+                            }
+                        },
+                        Event::KeyDown {keycode: Some(Keycode::F9), repeat: false, .. } => {
+                            match &state_path {
+                                Some(path) => load_state(&mut chip8, path),
+                                None => println!("Save states aren't available for a ROM read from stdin")
+                            }
+                        },
+                        Event::KeyDown {keycode: Some(Keycode::Backspace), repeat: false, .. } => {
+                            // Start the game over from the copy of the ROM read at startup.
+                            // reset() also releases any keys still held down
+                            if let Err(e) = chip8.reset_and_load(&buffer) {
+                                println!("Could not reload {}: {}", rom_name(filepath), e);
+                                break 'gameloop;
+                            }
+                            frame = 0;
+                            idle_frames = 0;
+                            println!("Reset");
+                            if paused {
+                                print_step(&chip8);
+                            }
+                        },
+                        Event::KeyDown {keycode: Some(Keycode::Space), repeat: false, .. } => {
+                            paused = !paused;
+                            if paused {
+                                println!("Paused");
+                                print_step(&chip8);
+                            } else {
+                                println!("Resumed");
+                            }
+                        },
+                        Event::KeyDown {keycode: Some(Keycode::Right), .. } if paused => {
+                            if !tick_or_report(&mut chip8, &mut trace) {
+                                break 'gameloop;
+                            }
+                            print_step(&chip8);
+                        },
+                        // Held keys make the OS send repeated KeyDowns. They're
+                        // not new presses, and handing them to the emulator
+                        // trips up games that poll SKP/SKNP in a tight loop
+                        Event::KeyDown {keycode: Some(k), repeat: false, .. } => {
+                            if let Some(btn) = k_to_btn(k) {
+                                chip8.set_key(btn, true);
+                            }
+                        },
+                        Event::KeyUp {keycode: Some(k), .. } => {
+                            if let Some(btn) = k_to_btn(k) {
+                                chip8.set_key(btn, false);
+                            }
+                        },
+                        Event::ControllerDeviceAdded {which, .. } if controller.is_none() => {
+                            match controller_subsystem.open(which) {
+                                Ok(c) => {
+                                    println!("Using controller {}", c.name());
+                                    controller = Some(c);
+                                },
+                                Err(e) => println!("Could not open controller: {}", e)
+                            }
+                        },
+                        Event::ControllerDeviceRemoved {which, .. }
+                            if controller.as_ref().is_some_and(|c| c.instance_id() == which) => {
+                            println!("Controller disconnected");
+                            controller = None;
+                        },
+                        Event::ControllerButtonDown {button, .. } => {
+                            if let Some(btn) = button_to_btn(button) {
+                                chip8.set_key(btn, true);
+                            }
+                        },
+                        Event::ControllerButtonUp {button, .. } => {
+                            if let Some(btn) = button_to_btn(button) {
+                                chip8.set_key(btn, false);
+                            }
+                        },
+                        _ => ()
                     }
-                },
-                Event::KeyUp {keycode: Some(k), .. } => {
-                    if let Some(btn) = k_to_btn(k) {
-                        chip8.keypress(btn, false);
+                }
+
+                if paused || !run_frame {
+                    break;
+                }
+
+                // Spread the frame's instructions as evenly as possible over the batches
+                let batch_ticks = options.ticks_per_frame * (batch + 1) / options.polls_per_frame
+                    - options.ticks_per_frame * batch / options.polls_per_frame;
+
+                for _ in 0..batch_ticks {
+                    // Nothing more to run, but keep the window up until the
+                    // player quits or resets
+                    if chip8.is_halted() {
+                        break;
+                    }
+
+                    if !tick_or_report(&mut chip8, &mut trace) {
+                        break 'gameloop;
+                    }
+                    speed_ticks += 1;
+
+                    if chip8.is_halted() {
+                        println!("Program halted at {:#06x}", chip8.program_counter());
+                        break;
+                    }
+
+                    // The rest of the frame's instructions would do nothing
+                    if chip8.is_waiting_for_vblank() {
+                        break;
                     }
-                },
-                _ => ()
+                }
             }
+
+            if paused || !run_frame {
+                break;
+            }
+
+            // tick_timers starts a new frame, so check for collisions first
+            collided |= chip8.collision_this_frame();
+            chip8.tick_timers();
+
+            if chip8.is_idle() {
+                idle_frames = idle_frames.saturating_add(1);
+            } else {
+                idle_frames = 0;
+            }
+
+            frame += 1;
+            speed_frames += 1;
+        }
+
+        if paused {
+            // Keep the screen up while nothing is running, and don't try to
+            // make up the time afterwards
+            lag = Duration::ZERO;
+            audio_device.pause();
+            chip8.render(&mut renderer);
+            thread::sleep(Duration::from_millis(IDLE_SLEEP_MS));
+            continue;
+        }
+
+        if chip8.is_beeping() {
+            audio_device.resume();
+        } else {
+            audio_device.pause();
+        }
+
+        if chip8.take_display_dirty() || window_changed {
+            chip8.render(&mut renderer);
+            window_changed = false;
+        } else {
+            // Presenting a frame is what waits for vsync, so without one
+            // wait for the next frame here instead of spinning
+            thread::sleep(FRAME_DURATION.saturating_sub(lag));
+        }
+
+        if let Some(stream) = &mut frame_stream {
+            stream.send_frame(&chip8);
+        }
+
+        if slow_collisions && collided {
+            thread::sleep(Duration::from_millis(options.collision_delay_ms));
+
+            // The hold is on purpose, so the game shouldn't race to catch up
+            last_time = Instant::now();
+        }
+
+        let idle = options.idle_threshold > 0 && idle_frames >= options.idle_threshold;
+        if chip8.is_paused() || chip8.is_halted() || idle {
+            thread::sleep(Duration::from_millis(IDLE_SLEEP_MS));
         }
 
-        for _ in 0..TICKS_PER_FRAME {
-            chip8.tick();
+        if options.show_speed && speed_start.elapsed() >= Duration::from_secs(1) {
+            let secs = speed_start.elapsed().as_secs_f64();
+            println!("{:.0} instructions/s, {:.1} frames/s", speed_ticks as f64 / secs, speed_frames as f64 / secs);
+
+            speed_start = Instant::now();
+            speed_ticks = 0;
+            speed_frames = 0;
         }
+    }
 
-        chip8.tick_timers();
-        draw_screen(&chip8, &mut canvas);
+    if let Some(log) = trace {
+        if let Err(e) = log.finish() {
+            println!("Could not write the trace: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        std::iter::once("chip8_sdl").chain(list.iter().copied()).map(String::from).collect()
+    }
+
+    #[test]
+    fn scale_must_be_between_one_and_the_max() {
+        assert_eq!(parse_args(&args(&["game.ch8", "--scale", "1"])).unwrap().scale, 1);
+        assert_eq!(parse_args(&args(&["game.ch8", "--scale", "64"])).unwrap().scale, MAX_SCALE);
+
+        for bad in ["0", "65", "4294967295", "-1", "big"] {
+            assert!(parse_args(&args(&["game.ch8", "--scale", bad])).is_err(), "--scale {} was accepted", bad);
+        }
     }
 }
@@ -0,0 +1,181 @@
+use std::io::{self, ErrorKind};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use tungstenite::{Message, WebSocket};
+
+// Longest a connecting viewer can hold up the frame with a slow or stalled
+// handshake before it's dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_millis(500);
+
+// Serves the display over a WebSocket so the game can be watched (and played)
+// from somewhere else, such as a browser. One viewer is connected at a time;
+// a new one can connect whenever the last has gone.
+//
+// Each frame the viewer is sent a binary message of the display width and
+// height (one byte each) followed by Emu::packed_display. The viewer sends
+// key changes back as two-byte binary messages: the key (0-F), then 1 for
+// pressed or 0 for released
+pub struct FrameStream {
+    listener: TcpListener,
+    client: Option<WebSocket<TcpStream>>
+}
+
+impl FrameStream {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+
+        // Checking for a viewer mustn't hold up the frame
+        listener.set_nonblocking(true)?;
+
+        Ok(Self { listener, client: None })
+    }
+
+    // Take any viewer waiting to connect, then return the key changes it has
+    // sent since the last call
+    pub fn poll_keys(&mut self) -> Vec<(usize, bool)> {
+        if self.client.is_none() {
+            self.accept();
+        }
+
+        let mut changes = Vec::new();
+
+        while let Some(client) = &mut self.client {
+            match client.read() {
+                Ok(Message::Binary(bytes)) => {
+                    if let [key, state] = bytes[..] {
+                        if key < 16 && state <= 1 {
+                            changes.push((key as usize, state == 1));
+                        }
+                    }
+                },
+                Ok(_) => (),
+                Err(tungstenite::Error::Io(e)) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => {
+                    println!("Stream viewer disconnected");
+                    self.client = None;
+                }
+            }
+        }
+
+        changes
+    }
+
+    // Send the current display to the viewer, if there is one
+    pub fn send_frame(&mut self, emu: &chip8_core::Emu) {
+        let Some(client) = &mut self.client else {
+            return;
+        };
+
+        let (width, height) = emu.display_dimensions();
+        let mut frame = vec![width as u8, height as u8];
+        frame.extend(emu.packed_display());
+
+        match client.send(Message::Binary(frame)) {
+            Ok(()) => (),
+            // The message is queued and goes out with a later send
+            Err(tungstenite::Error::Io(e)) if e.kind() == ErrorKind::WouldBlock => (),
+            Err(_) => {
+                println!("Stream viewer disconnected");
+                self.client = None;
+            }
+        }
+    }
+
+    fn accept(&mut self) {
+        let stream = match self.listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(_) => return
+        };
+
+        // Do the handshake in one go, giving up if the viewer stalls, then
+        // stop blocking for the frames
+        let result = stream.set_nonblocking(false)
+            .and_then(|()| stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)))
+            .and_then(|()| stream.set_write_timeout(Some(HANDSHAKE_TIMEOUT)))
+            .map_err(|e| e.to_string())
+            .and_then(|()| tungstenite::accept(stream).map_err(|e| e.to_string()))
+            .and_then(|ws| {
+                let stream = ws.get_ref();
+                stream.set_read_timeout(None)
+                    .and_then(|()| stream.set_write_timeout(None))
+                    .and_then(|()| stream.set_nonblocking(true))
+                    .map(|()| ws)
+                    .map_err(|e| e.to_string())
+            });
+
+        match result {
+            Ok(ws) => {
+                println!("Stream viewer connected");
+                self.client = Some(ws);
+            },
+            Err(e) => println!("Stream viewer could not connect: {}", e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::thread;
+    use std::time::Instant;
+
+    use chip8_core::Emu;
+
+    // Poll until the viewer has sent something, failing the test if it takes too long
+    fn wait_for_keys(stream: &mut FrameStream) -> Vec<(usize, bool)> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        loop {
+            let keys = stream.poll_keys();
+            if !keys.is_empty() {
+                return keys;
+            }
+            assert!(Instant::now() < deadline, "viewer never sent its key");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn viewer_gets_a_frame_and_sends_a_key() {
+        let mut stream = FrameStream::bind("127.0.0.1:0").unwrap();
+        let addr = stream.listener.local_addr().unwrap();
+
+        let viewer = thread::spawn(move || {
+            let (mut ws, _) = tungstenite::connect(format!("ws://{}", addr)).unwrap();
+            ws.send(Message::Binary(vec![0xA, 1])).unwrap();
+
+            match ws.read().unwrap() {
+                Message::Binary(frame) => frame,
+                other => panic!("expected a frame, got {:?}", other)
+            }
+        });
+
+        assert_eq!(wait_for_keys(&mut stream), [(0xA, true)]);
+
+        let mut emu = Emu::with_seed(0);
+        emu.load_test_pattern();
+        stream.send_frame(&emu);
+
+        let mut expected = vec![64, 32];
+        expected.extend(emu.packed_display());
+        assert_eq!(viewer.join().unwrap(), expected);
+    }
+
+    #[test]
+    fn stalled_handshake_doesnt_hold_up_the_frame() {
+        let mut stream = FrameStream::bind("127.0.0.1:0").unwrap();
+        let addr = stream.listener.local_addr().unwrap();
+
+        // Connect and start a request, but never finish it
+        let mut stalled = TcpStream::connect(addr).unwrap();
+        stalled.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        let start = Instant::now();
+        assert!(stream.poll_keys().is_empty());
+        assert!(start.elapsed() < HANDSHAKE_TIMEOUT * 4);
+        assert!(stream.client.is_none());
+    }
+}
@@ -0,0 +1,46 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use chip8_core::Emu;
+
+// Writes the machine state after every instruction to a CSV file, so a run
+// can be diffed against another emulator's to find where they part ways.
+//
+// The first line names the columns. Each line after it is the address the
+// instruction ran from and its opcode, then V0 through VF, I, and SP as they
+// were left afterwards, all in hex:
+//
+//     addr,op,v0,v1,...,vf,i,sp
+//     0200,00E0,00,00,...,00,0000,00
+pub struct TraceLog {
+    out: BufWriter<File>
+}
+
+impl TraceLog {
+    pub fn create(path: &str) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+
+        write!(out, "addr,op")?;
+        for reg in 0..16 {
+            write!(out, ",v{:x}", reg)?;
+        }
+        writeln!(out, ",i,sp")?;
+
+        Ok(Self { out })
+    }
+
+    // Log the instruction at addr that the emulator has just run
+    pub fn record(&mut self, addr: u16, emu: &Emu) -> io::Result<()> {
+        write!(self.out, "{:04X},{:04X}", addr, emu.last_opcode())?;
+        for reg in emu.registers() {
+            write!(self.out, ",{:02X}", reg)?;
+        }
+        writeln!(self.out, ",{:04X},{:02X}", emu.i_register(), emu.stack_pointer())
+    }
+
+    // Write out anything still buffered. Dropping the log does this too, but
+    // can't report a failure
+    pub fn finish(mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
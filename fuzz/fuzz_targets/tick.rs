@@ -0,0 +1,52 @@
+#![no_main]
+
+use chip8_core::{EmuBuilder, Quirks, StackOverflowPolicy};
+use libfuzzer_sys::fuzz_target;
+
+// However a ROM is put together, and whichever quirks it runs under, tick
+// should only ever return, never panic
+fuzz_target!(|data: &[u8]| {
+    // The first two bytes pick the quirks and the rest is the ROM
+    let (quirk_bits, rom) = match data {
+        [a, b, rom @ ..] => (u16::from_be_bytes([*a, *b]), rom),
+        _ => return
+    };
+    let bit = |n: u16| quirk_bits & (1 << n) != 0;
+
+    let quirks = Quirks {
+        add_imm_sets_vf: bit(0),
+        load_store_increments_i: bit(1),
+        shift_uses_vy: bit(2),
+        jump_uses_vx: bit(3),
+        strict_alignment: bit(4),
+        clip_sprites: bit(5),
+        display_wait: bit(6),
+        add_i_overflow_sets_vf: bit(7),
+        stack_overflow: match (quirk_bits >> 8) & 0x3 {
+            0 => StackOverflowPolicy::Error,
+            1 => StackOverflowPolicy::Saturate,
+            _ => StackOverflowPolicy::Wrap
+        },
+        wait_for_key_release: bit(10)
+    };
+
+    // XO-CHIP's 64KB makes the 16-bit addresses from F000 NNNN reachable
+    let memory_size = if bit(11) { 0x10000 } else { 4096 };
+
+    let mut emu = EmuBuilder::new().quirks(quirks).seed(0).memory_size(memory_size).build();
+    emu.set_trap_vf_reads(bit(12));
+
+    if emu.load(rom).is_err() {
+        return;
+    }
+
+    // Errors are fine, and the machine should still be usable after one, so
+    // keep going. Timers tick now and then so waits on them can finish
+    for tick in 0..10_000 {
+        let _ = emu.tick();
+
+        if tick % 10 == 9 {
+            emu.tick_timers();
+        }
+    }
+});